@@ -0,0 +1,180 @@
+/*
+   Generates the MSP message types from messages.json, and the C header for
+   the FFI layer
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+
+    generate_messages();
+
+    #[cfg(feature = "ffi")]
+    generate_header();
+}
+
+fn generate_messages() {
+
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+
+    // A copy of parser/messages.json, so that the packaged crate builds on
+    // its own; the msp tests check that the two agree
+    let json_path = Path::new(&crate_dir).join("messages.json");
+
+    let json = fs::read_to_string(&json_path)
+        .unwrap_or_else(|e| panic!("Unable to read {}: {}", json_path.display(), e));
+
+    let messages: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&json).expect("Unable to parse messages.json");
+
+    let mut code = String::new();
+
+    for (name, items) in &messages {
+
+        let mut id = None;
+        let mut comments = Vec::new();
+        let mut fields = Vec::new();
+
+        // Each item is a single-entry object: the ID, a comment, or a field
+        for item in items.as_array().expect("Message must be a list") {
+
+            let (key, value) = item.as_object().and_then(|o| o.iter().next())
+                .unwrap_or_else(|| panic!("Bad item in message {}", name));
+
+            match key.as_str() {
+                "ID" => id = value.as_u64(),
+                "comment" => comments.push(value.as_str().unwrap().to_string()),
+                _ => fields.push((key.clone(), field_type(name, value.as_str().unwrap())))
+            }
+        }
+
+        let id = id.unwrap_or_else(|| panic!("Message {} has no ID", name));
+
+        emit_message(&mut code, name, id, &comments, &fields);
+    }
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("messages.rs");
+
+    fs::write(out_path, code).unwrap();
+
+    println!("cargo:rerun-if-changed={}", json_path.display());
+}
+
+// Rust type and size in bytes for each type name used by msppg.py
+fn field_type(message: &str, name: &str) -> (&'static str, usize) {
+
+    match name {
+        "byte" => ("u8", 1),
+        "short" => ("i16", 2),
+        "float" => ("f32", 4),
+        "int" => ("i32", 4),
+        _ => panic!("Unknown type {} in message {}", name, message)
+    }
+}
+
+fn emit_message(
+    code: &mut String,
+    name: &str,
+    id: u64,
+    comments: &[String],
+    fields: &[(String, (&str, usize))]) {
+
+    // SET_RAW_RC => SetRawRc
+    let type_name: String = name.split('_').map(|word| {
+        let lower = word.to_lowercase();
+        lower[..1].to_uppercase() + &lower[1..]
+    }).collect();
+
+    // Byte offset of each field in the payload
+    let offsets: Vec<usize> = fields.iter()
+        .scan(0, |offset, (_, (_, size))| { *offset += size; Some(*offset - size) })
+        .collect();
+
+    let size: usize = fields.iter().map(|(_, (_, size))| size).sum();
+
+    let decls: Vec<String> = fields.iter()
+        .map(|(field, (rust_type, _))| format!("    pub {}: {}", field, rust_type))
+        .collect();
+
+    let encodes: Vec<String> = fields.iter().zip(&offsets)
+        .map(|((field, (_, n)), k)| format!(
+                "        buf[{}..{}].copy_from_slice(&self.{}.to_le_bytes());",
+                k, k + n, field))
+        .collect();
+
+    let decodes: Vec<String> = fields.iter().zip(&offsets)
+        .map(|((field, (rust_type, n)), k)| format!(
+                "            {}: {}::from_le_bytes(payload[{}..{}].try_into().unwrap())",
+                field, rust_type, k, k + n))
+        .collect();
+
+    writeln!(code).unwrap();
+
+    for comment in comments {
+        writeln!(code, "// {}", comment.trim()).unwrap();
+    }
+
+    writeln!(code, "#[derive(Clone,Copy,Debug,Default,PartialEq)]").unwrap();
+    writeln!(code, "pub struct {} {{\n", type_name).unwrap();
+    writeln!(code, "{}\n}}\n", decls.join(",\n")).unwrap();
+
+    writeln!(code, "impl Payload for {} {{\n", type_name).unwrap();
+    writeln!(code, "    const ID: u16 = {};", id).unwrap();
+    writeln!(code, "    const SIZE: usize = {};\n", size).unwrap();
+
+    writeln!(code, "    fn encode(&self, buf: &mut [u8]) {{\n").unwrap();
+    writeln!(code, "{}\n    }}\n", encodes.join("\n")).unwrap();
+
+    writeln!(code, "    fn decode(payload: &[u8]) -> Option<Self> {{\n").unwrap();
+    writeln!(code, "        if payload.len() < Self::SIZE {{").unwrap();
+    writeln!(code, "            return None;\n        }}\n").unwrap();
+    writeln!(code, "        Some({} {{", type_name).unwrap();
+    writeln!(code, "{}\n        }})\n    }}\n}}", decodes.join(",\n")).unwrap();
+}
+
+// The header goes into OUT_DIR, where the ffi tests check that the one in
+// include/ is up to date; building with HACKFLIGHT_UPDATE_HEADER set copies
+// it there
+#[cfg(feature = "ffi")]
+fn generate_header() {
+
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("Unable to read cbindgen.toml");
+
+    let header = cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate C header");
+
+    header.write_to_file(Path::new(&env::var("OUT_DIR").unwrap()).join("hackflight.h"));
+
+    if env::var_os("HACKFLIGHT_UPDATE_HEADER").is_some() {
+        header.write_to_file(format!("{}/include/hackflight.h", crate_dir));
+    }
+
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=HACKFLIGHT_UPDATE_HEADER");
+}
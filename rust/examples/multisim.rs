@@ -20,24 +20,36 @@ extern crate hackflight;
 
 use std::net::UdpSocket;
 
-use hackflight::Demands;
+use hackflight::clock::LoopConfig;
 use hackflight::Motors;
 use hackflight::VehicleState;
+use hackflight::failsafe;
+use hackflight::modes;
+use hackflight::modes::FlightMode;
+use hackflight::modes::ModeRange;
 use hackflight::pids;
-use hackflight::step;
+use hackflight::receiver;
 use hackflight::mixers::quadxbf;
-use hackflight::utils::rescale;
+use hackflight::mixers::saturation::Priority;
+use hackflight::mixers::saturation::SaturationConfig;
 use hackflight::utils::rad2deg;
 
 const RATE_KP  : f32 = 1.441305;
 const RATE_KI  : f32 = 48.8762;
 const RATE_KD  : f32 = 0.021160;
 const RATE_KF  : f32 = 0.0165048;
-const LEVEL_KP : f32 = 0.0;
+const LEVEL_KP : f32 = 3.0; // for failsafe, which levels in Angle mode
 
 const ALT_HOLD_KP : f32 = 7.5e-2;
 const ALT_HOLD_KI : f32 = 1.5e-1;
 
+// Loop rate the gains above were tuned for
+const LOOP_RATE_HZ : u32 = 1_000_000;
+
+// How the mixer limits the motors when the demands saturate them
+const SATURATION : SaturationConfig =
+    SaturationConfig { priority: Priority::RollPitch, airmode: false };
+
 fn main() -> std::io::Result<()> {
 
     const IN_BUF_SIZE:usize  = 17*8; // 17 doubles in
@@ -72,20 +84,22 @@ fn main() -> std::io::Result<()> {
         }
     }
 
-    fn demands_from_telemetry(buf:[u8; IN_BUF_SIZE]) -> Demands {
-        Demands {
-            throttle:read_float(buf, 13),
-            roll:read_float(buf, 14),
-            pitch:read_float(buf, 15),
-            yaw:read_float(buf, 16)
+    // Sticks [-1,+1] => channels [1000,2000], with aux switches off.  The
+    // receiver reverses yaw, so it is reversed here too, keeping the sim's
+    // yaw sign.
+    fn channels_from_telemetry(buf:[u8; IN_BUF_SIZE]) -> [u16; receiver::CHANNEL_COUNT] {
+        const SIGNS: [f32; 4] = [1.0, 1.0, 1.0, -1.0];
+        let mut channels = [1000u16; receiver::CHANNEL_COUNT];
+        for (k, channel) in channels.iter_mut().take(4).enumerate() {
+            *channel = (1500.0 + 500.0 * SIGNS[k] * read_float(buf, 13 + k)) as u16;
         }
+        channels
     }
 
     fn write_motors(motors:Motors) -> [u8; OUT_BUF_SIZE] {
         let mut buf = [0u8; OUT_BUF_SIZE]; 
-        let motorvals = [motors.m1, motors.m2, motors.m3, motors.m4];
-        for j in 0..4 {
-            let bytes = (motorvals[j] as f64).to_le_bytes();
+        for (j, value) in motors.as_slice().iter().take(4).enumerate() {
+            let bytes = (*value as f64).to_le_bytes();
             for k in 0..8 {
                 buf[j*8+k] = bytes[k];
             }
@@ -101,11 +115,26 @@ fn main() -> std::io::Result<()> {
 
     println!("Hit the Play button ...");
 
-    let alt_hold_pid = pids::make_alt_hold(ALT_HOLD_KP, ALT_HOLD_KI);
+    let alt_hold_config = pids::DEFAULT_ALT_HOLD_CONFIG;
+
+    let alt_hold_pid = pids::make_alt_hold(ALT_HOLD_KP, ALT_HOLD_KI, &alt_hold_config).unwrap();
+
+    let loop_config = LoopConfig { rate_hz: LOOP_RATE_HZ };
+
+    let angle_pid =
+        pids::make_angle(
+            RATE_KP, RATE_KI, RATE_KD, RATE_KF, LEVEL_KP, &loop_config, &pids::DEFAULT_ANGLE_CONFIG)
+            .unwrap();
 
-    let angle_pid = pids::make_angle(RATE_KP, RATE_KI, RATE_KD, RATE_KF, LEVEL_KP);
+    let mixer = quadxbf::QuadXbf { saturation: SATURATION };
 
-    let mixer = quadxbf::QuadXbf { };
+    let mut receiver = receiver::make();
+
+    let mut failsafe = failsafe::make(failsafe::DEFAULT_CONFIG).unwrap();
+
+    // The sim has no aux switches, so hold altitude over the whole range
+    let mut modes = modes::make();
+    modes.add_range(ModeRange { mode: FlightMode::AltHold, aux: 0, start: 900, end: 2100 });
 
     let mut pids: [pids::Controller; 2] = [angle_pid, alt_hold_pid];
 
@@ -129,16 +158,24 @@ fn main() -> std::io::Result<()> {
         let vstate = state_from_telemetry(in_buf);
 
         // Get incoming stick demands
-        let mut stick_demands = demands_from_telemetry(in_buf);
+        receiver.set_values(&channels_from_telemetry(in_buf), usec, false, 1000, 2000);
+        let stick_demands = receiver.get_demands();
+
+        // Level and descend on loss of signal, using the sim's altitude
+        failsafe.update(usec, receiver.have_signal(usec));
+        let stick_demands = failsafe.get_demands(&stick_demands, &alt_hold_config, true);
+
+        modes.update(&receiver, &failsafe, true);
 
-        // Reset PID controllers on zero throttle
-        let pid_reset = stick_demands.throttle < 0.05;
+        // Reset PID controllers on zero throttle, from the raw [-1,+1] stick
+        let pid_reset = read_float(in_buf, 13) < 0.05;
 
-        // Rescale throttle [-1,+1] => [0,1]
-        stick_demands.throttle = rescale(stick_demands.throttle, -1.0, 1.0, 0.0, 1.0);
+        // The sim has no arming switch, so only failsafe disarms
+        let armed = !failsafe.should_disarm();
 
-        // let motors = Motors {m1: 0.0, m2: 0.0, m3:0.0, m4:0.0};
-        let motors = step(&stick_demands, &vstate, &mut pids, &pid_reset, &usec, &mixer);
+        let motors =
+            modes.step(&stick_demands, &vstate, &mut pids, &pid_reset, &armed, &usec, &mixer)
+            .unwrap();
 
         let out_buf = write_motors(motors);
 
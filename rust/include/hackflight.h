@@ -0,0 +1,205 @@
+#ifndef HACKFLIGHT_H
+#define HACKFLIGHT_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define HfMAX_MOTORS 8
+
+typedef enum HfMixerType {
+  HfMixerType_QuadXbf,
+  HfMixerType_QuadX,
+  HfMixerType_QuadP,
+  HfMixerType_HexX,
+  HfMixerType_HexP,
+  HfMixerType_Y6,
+  HfMixerType_OctoX,
+  HfMixerType_X8,
+  HfMixerType_Tricopter,
+} HfMixerType;
+
+typedef enum HfPriority {
+  HfPriority_RollPitch,
+  HfPriority_Equal,
+} HfPriority;
+
+typedef struct HfSaturationConfig {
+  enum HfPriority priority;
+  bool airmode;
+} HfSaturationConfig;
+
+typedef enum HfProtocol_Tag {
+  HfProtocol_Pwm,
+  HfProtocol_Dshot,
+} HfProtocol_Tag;
+
+typedef struct HfProtocol_HfPwm_Body {
+  uint16_t stop_usec;
+  uint16_t min_usec;
+  uint16_t max_usec;
+} HfProtocol_HfPwm_Body;
+
+typedef struct HfProtocol {
+  HfProtocol_Tag tag;
+  union {
+    HfProtocol_HfPwm_Body pwm;
+  };
+} HfProtocol;
+
+typedef struct HfOutputConfig {
+  float idle;
+  float thrust_linear;
+  float sag_compensation;
+  float reference_voltage;
+  struct HfProtocol protocol;
+} HfOutputConfig;
+
+typedef struct HfDemands {
+  float throttle;
+  float roll;
+  float pitch;
+  float yaw;
+} HfDemands;
+
+typedef struct HfVehicleState {
+  float x;
+  float dx;
+  float y;
+  float dy;
+  float z;
+  float dz;
+  float phi;
+  float dphi;
+  float theta;
+  float dtheta;
+  float psi;
+  float dpsi;
+} HfVehicleState;
+
+typedef struct HfMotors {
+  uintptr_t count;
+  float values[HfMAX_MOTORS];
+  float servo;
+} HfMotors;
+
+typedef struct HfCommands {
+  uintptr_t count;
+  uint16_t values[HfMAX_MOTORS];
+  uint16_t servo_usec;
+} HfCommands;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Removes all controllers and goes back to the quad-X (Betaflight ordering)
+ * mixer, with the default saturation handling and motor output
+ *
+ * # Safety
+ *
+ * Not reentrant: no other hf_ function may be running, e.g. on another
+ * thread or in an interrupt handler.
+ */
+void hf_reset(void);
+
+/**
+ * Chooses the frame that hf_step() mixes for
+ *
+ * # Safety
+ *
+ * Not reentrant: no other hf_ function may be running, e.g. on another
+ * thread or in an interrupt handler.
+ */
+void hf_set_mixer(enum HfMixerType mixer);
+
+/**
+ * Chooses how hf_step() limits the motors when the demands saturate them
+ *
+ * # Safety
+ *
+ * Not reentrant: no other hf_ function may be running, e.g. on another
+ * thread or in an interrupt handler.
+ */
+void hf_set_saturation(struct HfSaturationConfig config);
+
+/**
+ * Chooses how hf_output() turns motor values into ESC commands; returns
+ * false, keeping the previous choice, if the configuration is invalid
+ *
+ * # Safety
+ *
+ * Not reentrant: no other hf_ function may be running, e.g. on another
+ * thread or in an interrupt handler.
+ */
+bool hf_set_output(struct HfOutputConfig config);
+
+/**
+ * Adds an angle (level) PID controller running at the given loop rate, with
+ * the default tuning; returns false if there is no room left, the level
+ * gain isn't positive, or the loop rate is too low for the default filters
+ *
+ * # Safety
+ *
+ * Not reentrant: no other hf_ function may be running, e.g. on another
+ * thread or in an interrupt handler.
+ */
+bool hf_add_angle_pid(float k_rate_p,
+                      float k_rate_i,
+                      float k_rate_d,
+                      float k_rate_f,
+                      float k_level_p,
+                      uint32_t rate_hz);
+
+/**
+ * Adds an altitude-hold PID controller; returns false if there is no room
+ * left
+ *
+ * # Safety
+ *
+ * Not reentrant: no other hf_ function may be running, e.g. on another
+ * thread or in an interrupt handler.
+ */
+bool hf_add_alt_hold_pid(float k_p,
+                         float k_i);
+
+/**
+ * As crate::step(), running the controllers in the order they were added
+ * and mixing for the frame chosen by hf_set_mixer(), limited as chosen by
+ * hf_set_saturation()
+ *
+ * # Safety
+ *
+ * Not reentrant: no other hf_ function may be running, e.g. on another
+ * thread or in an interrupt handler.  The pointers must be valid.
+ */
+void hf_step(const struct HfDemands *stick_demands,
+             const struct HfVehicleState *vstate,
+             bool pid_reset,
+             bool armed,
+             uint32_t usec,
+             struct HfMotors *motors);
+
+/**
+ * ESC commands for the motor values from hf_step(), as chosen by
+ * hf_set_output(); the pack voltage is zero when not measured
+ *
+ * # Safety
+ *
+ * Not reentrant: no other hf_ function may be running, e.g. on another
+ * thread or in an interrupt handler.  The pointers must be valid.
+ */
+void hf_output(const struct HfMotors *motors,
+               bool armed,
+               float voltage,
+               struct HfCommands *commands);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* HACKFLIGHT_H */
@@ -0,0 +1,192 @@
+/*
+   Arming and disarming, after C++ Logic::updateArmingStatus()
+
+   The vehicle is ready to arm only when the arming switch (aux1) has been
+   seen off, the throttle is down, the vehicle is level, the gyro has
+   finished calibrating, and the receiver has a signal with failsafe clear.
+   Flipping the switch on while ready arms; flipping it off disarms.  A
+   failsafe landing that runs to completion disarms, and the switch must
+   then be turned off again before re-arming.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::VehicleState;
+use crate::failsafe::Failsafe;
+use crate::receiver::Receiver;
+
+pub const MAX_ARMING_ANGLE_DEG: f32 = 25.0;
+
+// Reasons arming is blocked, as returned by Arming::blockers()
+pub const BLOCKED_SWITCH_NOT_OFF: u8 = 0x01;
+pub const BLOCKED_THROTTLE_UP: u8 = 0x02;
+pub const BLOCKED_ANGLE: u8 = 0x04;
+pub const BLOCKED_GYRO_CALIBRATING: u8 = 0x08;
+pub const BLOCKED_NO_SIGNAL: u8 = 0x10;
+pub const BLOCKED_FAILSAFE: u8 = 0x20;
+
+// Arming switch channel (aux1) and positions, as in the C++
+pub const SWITCH_AUX: usize = 0;
+const SWITCH_OFF_MIN: f32 = 900.0;
+const SWITCH_OFF_MAX: f32 = 1200.0;
+pub const SWITCH_ON_MIN: f32 = 1500.0;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum ArmingStatus {
+
+    Unready,
+    Ready,
+    Armed,
+
+    // Disarmed by failsafe, until the link recovers
+    Failsafe
+}
+
+#[derive(Clone)]
+pub struct Arming {
+
+    status: ArmingStatus,
+    switch_was_off: bool,
+    switch_was_on: bool,
+    blockers: u8
+}
+
+impl Arming {
+
+    pub fn update(
+        &mut self,
+        usec: u32,
+        receiver: &Receiver,
+        vstate: &VehicleState,
+        gyro_calibrating: bool,
+        failsafe: &Failsafe) -> ArmingStatus {
+
+        let aux1 = receiver.aux(SWITCH_AUX);
+
+        // Avoid arming if the switch starts on
+        if !self.switch_was_off {
+            self.switch_was_off = aux1 > SWITCH_OFF_MIN && aux1 < SWITCH_OFF_MAX;
+        }
+
+        self.blockers = self.check(usec, receiver, vstate, gyro_calibrating, failsafe);
+
+        let switch_on = aux1 > SWITCH_ON_MIN;
+        let switched_on = switch_on && !self.switch_was_on;
+
+        self.switch_was_on = switch_on;
+
+        self.status = match self.status {
+
+            ArmingStatus::Unready | ArmingStatus::Ready => {
+                if self.blockers != 0 {
+                    ArmingStatus::Unready
+                } else if switched_on {
+                    ArmingStatus::Armed
+                } else {
+                    ArmingStatus::Ready
+                }
+            }
+
+            // Failsafe keeps us armed while it levels and descends
+            ArmingStatus::Armed => {
+                if failsafe.should_disarm() {
+                    self.switch_was_off = false;
+                    ArmingStatus::Failsafe
+                } else if !switch_on {
+                    if self.blockers != 0 { ArmingStatus::Unready } else { ArmingStatus::Ready }
+                } else {
+                    ArmingStatus::Armed
+                }
+            }
+
+            ArmingStatus::Failsafe => {
+                if failsafe.is_active() {
+                    ArmingStatus::Failsafe
+                } else {
+                    ArmingStatus::Unready
+                }
+            }
+        };
+
+        self.status
+    }
+
+    pub fn status(&self) -> ArmingStatus {
+
+        self.status
+    }
+
+    pub fn is_armed(&self) -> bool {
+
+        self.status == ArmingStatus::Armed
+    }
+
+    // Bitmask of BLOCKED_* reasons from the last update, zero when nothing
+    // blocks arming
+    pub fn blockers(&self) -> u8 {
+
+        self.blockers
+    }
+
+    fn check(
+        &self,
+        usec: u32,
+        receiver: &Receiver,
+        vstate: &VehicleState,
+        gyro_calibrating: bool,
+        failsafe: &Failsafe) -> u8 {
+
+        let mut blockers = 0;
+
+        if !self.switch_was_off {
+            blockers |= BLOCKED_SWITCH_NOT_OFF;
+        }
+
+        if !receiver.throttle_is_down() {
+            blockers |= BLOCKED_THROTTLE_UP;
+        }
+
+        if vstate.phi.abs() >= MAX_ARMING_ANGLE_DEG
+            || vstate.theta.abs() >= MAX_ARMING_ANGLE_DEG {
+            blockers |= BLOCKED_ANGLE;
+        }
+
+        if gyro_calibrating {
+            blockers |= BLOCKED_GYRO_CALIBRATING;
+        }
+
+        if !receiver.have_signal(usec) {
+            blockers |= BLOCKED_NO_SIGNAL;
+        }
+
+        if failsafe.is_active() {
+            blockers |= BLOCKED_FAILSAFE;
+        }
+
+        blockers
+    }
+}
+
+pub fn make() -> Arming {
+
+    Arming {
+        status: ArmingStatus::Unready,
+        switch_was_off: false,
+        switch_was_on: false,
+        blockers: 0
+    }
+}
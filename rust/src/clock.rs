@@ -17,8 +17,27 @@
  */
 
 
-const RATE: u32 = 1_000_000;
+use crate::pids::InvalidConfig;
+use crate::pids::check;
 
-const PERIOD: u32 = 1_000_000 / RATE;
+// Rate at which the control loop (filters, PID controllers) is run
+#[derive(Clone,Copy,Debug)]
+pub struct LoopConfig {
 
-pub const DT: f32 = (PERIOD as f32) * 1e-6;
+    pub rate_hz: u32
+}
+
+impl LoopConfig {
+
+    // Constructors taking a LoopConfig call this, since dt() is infinite
+    // for a zero rate
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+
+        check(self.rate_hz > 0, "rate_hz")
+    }
+
+    pub fn dt(&self) -> f32 {
+
+        1.0 / (self.rate_hz as f32)
+    }
+}
@@ -0,0 +1,433 @@
+/*
+   Saving and loading the flight configuration
+
+   A configuration is saved along with the schema version it was written
+   in: as a compact binary blob for flash, or as TOML or JSON for desktop
+   tools.  Loading a configuration saved by older firmware migrates it to
+   the current schema; one saved by newer firmware is refused.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::clock::LoopConfig;
+use crate::failsafe;
+use crate::failsafe::Failsafe;
+use crate::mixers::MixerType;
+use crate::mixers::Preset;
+use crate::mixers::saturation;
+use crate::mixers::saturation::SaturationConfig;
+use crate::modes;
+use crate::modes::FlightMode;
+use crate::modes::ModeRange;
+use crate::modes::Modes;
+use crate::output;
+use crate::output::Output;
+use crate::output::OutputConfig;
+use crate::pids;
+use crate::pids::AltHoldConfig;
+use crate::pids::AnglePidConfig;
+use crate::pids::InvalidConfig;
+use crate::rates;
+use crate::rates::RateProfile;
+
+// Bump when the layout of FlightConfig changes, keeping the old layout in a
+// module of its own with a migration to the new one; see load()
+pub const SCHEMA_VERSION: u16 = 1;
+
+// Room for the binary blob of any configuration
+pub const MAX_BYTES: usize = 512;
+
+// As passed to pids::make_angle()
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub struct AngleGains {
+
+    pub k_rate_p: f32,
+    pub k_rate_i: f32,
+    pub k_rate_d: f32,
+    pub k_rate_f: f32,
+    pub k_level_p: f32
+}
+
+// As passed to pids::make_alt_hold()
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub struct AltHoldGains {
+
+    pub k_p: f32,
+    pub k_i: f32
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub struct FlightConfig {
+
+    pub angle_gains: AngleGains,
+    pub angle: AnglePidConfig,
+
+    pub alt_hold_gains: AltHoldGains,
+    pub alt_hold: AltHoldConfig,
+
+    pub rates: RateProfile,
+
+    pub mixer: MixerType,
+
+    // Slots holding UNUSED_RANGE (or any empty range) are skipped
+    pub modes: [ModeRange; modes::MAX_RANGES],
+
+    pub failsafe: failsafe::Config,
+
+    pub output: OutputConfig,
+
+    // How the mixer limits the motors when the demands saturate them
+    pub saturation: SaturationConfig
+}
+
+pub const UNUSED_RANGE: ModeRange = ModeRange {
+    mode: FlightMode::Acro,
+    aux: 0,
+    start: 0,
+    end: 0
+};
+
+// Gains as tuned in the multicopter simulator, with the C++ firmware's level
+// gain and AltHold on aux2 high, since aux1 is the arming switch
+pub const DEFAULT_CONFIG: FlightConfig = FlightConfig {
+
+    angle_gains: AngleGains {
+        k_rate_p: 1.441305,
+        k_rate_i: 48.8762,
+        k_rate_d: 0.021160,
+        k_rate_f: 0.0165048,
+        k_level_p: 3.0
+    },
+    angle: pids::DEFAULT_ANGLE_CONFIG,
+
+    alt_hold_gains: AltHoldGains { k_p: 7.5e-2, k_i: 1.5e-1 },
+    alt_hold: pids::DEFAULT_ALT_HOLD_CONFIG,
+
+    rates: rates::DEFAULT_PROFILE,
+
+    mixer: MixerType::QuadXbf,
+
+    modes: [
+        ModeRange { mode: FlightMode::AltHold, aux: 1, start: 1700, end: 2100 },
+        UNUSED_RANGE,
+        UNUSED_RANGE,
+        UNUSED_RANGE,
+        UNUSED_RANGE,
+        UNUSED_RANGE,
+        UNUSED_RANGE,
+        UNUSED_RANGE
+    ],
+
+    failsafe: failsafe::DEFAULT_CONFIG,
+
+    output: output::DEFAULT_CONFIG,
+
+    saturation: saturation::DEFAULT_CONFIG
+};
+
+impl FlightConfig {
+
+    // Every section, as far as it can be checked without the loop rate,
+    // which make_angle() checks the filter cutoffs against; the saturation
+    // config has no invalid values
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+
+        pids::check(self.angle_gains.k_level_p > 0.0, "k_level_p")?;
+
+        // No cutoff is above the Nyquist frequency of the fastest loop
+        self.angle.validate(&LoopConfig { rate_hz: u32::MAX })?;
+
+        self.alt_hold.validate()?;
+        self.rates.validate()?;
+        self.make_modes()?;
+        self.failsafe.validate()?;
+        self.output.validate()
+    }
+
+    pub fn make_angle(&self, loop_config: &LoopConfig) -> Result<pids::Controller, InvalidConfig> {
+
+        let g = &self.angle_gains;
+
+        let mut pid = pids::make_angle(
+            g.k_rate_p, g.k_rate_i, g.k_rate_d, g.k_rate_f, g.k_level_p, loop_config, &self.angle)?;
+
+        pids::set_rates(&mut pid, &self.rates)?;
+
+        Ok(pid)
+    }
+
+    pub fn make_alt_hold(&self) -> Result<pids::Controller, InvalidConfig> {
+
+        pids::make_alt_hold(self.alt_hold_gains.k_p, self.alt_hold_gains.k_i, &self.alt_hold)
+    }
+
+    pub fn make_modes(&self) -> Result<Modes, InvalidConfig> {
+
+        let mut modes = modes::make();
+
+        for range in self.modes.iter().filter(|range| range.start < range.end) {
+            pids::check(modes.add_range(*range), "modes")?;
+        }
+
+        Ok(modes)
+    }
+
+    pub fn make_failsafe(&self) -> Result<Failsafe, InvalidConfig> {
+
+        failsafe::make(self.failsafe)
+    }
+
+    pub fn make_output(&self) -> Result<Output, InvalidConfig> {
+
+        output::make(self.output)
+    }
+
+    pub fn mixer(&self) -> Preset {
+
+        self.mixer.mixer(&self.saturation)
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Error {
+
+    // Saved by newer firmware, or not a configuration at all
+    UnsupportedVersion(u16),
+
+    // Malformed data, or a buffer too small to save into
+    Encoding,
+
+    // Well-formed, but out of range, e.g. from a hand-edited file
+    Invalid(InvalidConfig)
+}
+
+// The version goes first, so that it can be read before the layout is known
+#[derive(Serialize,Deserialize)]
+struct Saved<T> {
+
+    version: u16,
+    config: T
+}
+
+#[derive(Deserialize)]
+struct Version {
+
+    version: u16
+}
+
+// Returns the number of bytes used
+pub fn to_bytes(config: &FlightConfig, buf: &mut [u8]) -> Result<usize, Error> {
+
+    postcard::to_slice(&saved(config), buf)
+        .map(|used| used.len())
+        .map_err(|_| Error::Encoding)
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<FlightConfig, Error> {
+
+    load(&Binary(bytes))
+}
+
+#[cfg(feature = "serde-std")]
+pub fn to_toml(config: &FlightConfig) -> Result<String, Error> {
+
+    toml::to_string(&saved(config)).map_err(|_| Error::Encoding)
+}
+
+#[cfg(feature = "serde-std")]
+pub fn from_toml(text: &str) -> Result<FlightConfig, Error> {
+
+    load(&Toml(text))
+}
+
+#[cfg(feature = "serde-std")]
+pub fn to_json(config: &FlightConfig) -> Result<String, Error> {
+
+    serde_json::to_string_pretty(&saved(config)).map_err(|_| Error::Encoding)
+}
+
+#[cfg(feature = "serde-std")]
+pub fn from_json(text: &str) -> Result<FlightConfig, Error> {
+
+    load(&Json(text))
+}
+
+fn saved(config: &FlightConfig) -> Saved<&FlightConfig> {
+
+    Saved { version: SCHEMA_VERSION, config }
+}
+
+// Older versions decode into their own layout and migrate forward; the
+// result is validated, so that a corrupt config fails here rather than in
+// flight.  Each older version gets an arm here, e.g.
+//
+//     1 => upgrade::<v1::FlightConfig>(data),
+fn load<D: Decode>(data: &D) -> Result<FlightConfig, Error> {
+
+    let Version { version } = data.decode()?;
+
+    let config = match version {
+
+        SCHEMA_VERSION => data.decode::<Saved<FlightConfig>>().map(|saved| saved.config),
+
+        _ => Err(Error::UnsupportedVersion(version))
+    }?;
+
+    config.validate().map_err(Error::Invalid)?;
+
+    Ok(config)
+}
+
+// Unused until the layout first changes
+#[cfg_attr(not(test), allow(dead_code))]
+fn upgrade<L: Layout>(data: &impl Decode) -> Result<FlightConfig, Error> {
+
+    data.decode::<Saved<L>>().map(|saved| saved.config.upgrade())
+}
+
+// The layout of an earlier version, which migrates to the next version's
+// and from there to the current one.  When the layout changes, the one
+// being replaced moves into a module of its own (e.g. mod v1), and the
+// newest Layout upgrades to it instead of to FlightConfig.
+#[cfg_attr(not(test), allow(dead_code))]
+trait Layout: DeserializeOwned {
+
+    fn upgrade(self) -> FlightConfig;
+}
+
+// Decoding the same data more than once, as different types
+trait Decode {
+
+    fn decode<T: DeserializeOwned>(&self) -> Result<T, Error>;
+}
+
+struct Binary<'a>(&'a [u8]);
+
+impl Decode for Binary<'_> {
+
+    // Trailing bytes are ignored, so that the version can be read alone
+    fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
+
+        postcard::take_from_bytes(self.0)
+            .map(|(t, _)| t)
+            .map_err(|_| Error::Encoding)
+    }
+}
+
+#[cfg(feature = "serde-std")]
+struct Toml<'a>(&'a str);
+
+#[cfg(feature = "serde-std")]
+impl Decode for Toml<'_> {
+
+    fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
+
+        toml::from_str(self.0).map_err(|_| Error::Encoding)
+    }
+}
+
+#[cfg(feature = "serde-std")]
+struct Json<'a>(&'a str);
+
+#[cfg(feature = "serde-std")]
+impl Decode for Json<'_> {
+
+    fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
+
+        serde_json::from_str(self.0).map_err(|_| Error::Encoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    use super::*;
+
+    // A stand-in for an earlier layout, without the saturation config
+    #[derive(Serialize,Deserialize)]
+    struct Unsaturated {
+
+        angle_gains: AngleGains,
+        angle: AnglePidConfig,
+        alt_hold_gains: AltHoldGains,
+        alt_hold: AltHoldConfig,
+        rates: RateProfile,
+        mixer: MixerType,
+        modes: [ModeRange; modes::MAX_RANGES],
+        failsafe: failsafe::Config,
+        output: OutputConfig
+    }
+
+    impl Layout for Unsaturated {
+
+        fn upgrade(self) -> FlightConfig {
+
+            FlightConfig {
+                angle_gains: self.angle_gains,
+                angle: self.angle,
+                alt_hold_gains: self.alt_hold_gains,
+                alt_hold: self.alt_hold,
+                rates: self.rates,
+                mixer: self.mixer,
+                modes: self.modes,
+                failsafe: self.failsafe,
+                output: self.output,
+                saturation: saturation::DEFAULT_CONFIG
+            }
+        }
+    }
+
+    #[test]
+    fn older_layout_is_upgraded() {
+
+        let d = DEFAULT_CONFIG;
+
+        let old = Unsaturated {
+            angle_gains: AngleGains { k_level_p: 4.0, ..d.angle_gains },
+            angle: d.angle,
+            alt_hold_gains: d.alt_hold_gains,
+            alt_hold: d.alt_hold,
+            rates: d.rates,
+            mixer: MixerType::HexX,
+            modes: d.modes,
+            failsafe: d.failsafe,
+            output: d.output
+        };
+
+        let mut buf = [0; MAX_BYTES];
+
+        let bytes = postcard::to_slice(&Saved { version: 0, config: old }, &mut buf).unwrap();
+
+        let expected = FlightConfig {
+            angle_gains: AngleGains { k_level_p: 4.0, ..d.angle_gains },
+            mixer: MixerType::HexX,
+            ..d
+        };
+
+        assert_eq!(upgrade::<Unsaturated>(&Binary(bytes)), Ok(expected));
+
+        // Without an arm in load(), the version is refused
+        assert_eq!(load(&Binary(bytes)), Err(Error::UnsupportedVersion(0)));
+    }
+}
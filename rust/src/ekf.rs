@@ -0,0 +1,286 @@
+/*
+   Hackflight extended Kalman filter for position and velocity
+
+   State is world-frame (x forward, z up) position and velocity in meters and
+   meters per second.  Accelerometer readings, rotated into the world frame
+   using the attitude from the IMU, drive the prediction step; barometer,
+   rangefinder (e.g. VL53L5) and optical flow (e.g. PAA3905) readings correct
+   it.  Measurements are fused one scalar at a time, so no matrix inversion is
+   needed.
+
+   Copyright (C) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::VehicleState;
+use crate::math;
+use crate::utils::deg2rad;
+
+const GRAVITY: f32 = 9.81;
+
+// Process noise
+const ACCEL_STDEV: f32 = 0.5;
+
+// Measurement noise
+const BARO_STDEV: f32 = 0.5;
+const RANGE_STDEV: f32 = 0.02;
+const FLOW_STDEV: f32 = 0.5;
+
+// Initial uncertainty
+const POSITION_STDEV_INITIAL: f32 = 1.0;
+const VELOCITY_STDEV_INITIAL: f32 = 1.0;
+
+// Rangefinder readings are ignored beyond this distance or tilt
+const RANGE_MAX: f32 = 4.0;
+const TILT_COS_MIN: f32 = 0.5;
+
+// Optical flow is unreliable close to the ground
+const FLOW_HEIGHT_MIN: f32 = 0.1;
+
+// Optical flow sensor resolution (pixels) and field of view (radians)
+const FLOW_NPIX: f32 = 35.0;
+const FLOW_THETAPIX: f32 = 0.71674;
+
+const X: usize = 0;
+const DX: usize = 1;
+const Y: usize = 2;
+const DY: usize = 3;
+const Z: usize = 4;
+const DZ: usize = 5;
+
+const N: usize = 6;
+
+type Vector = [f32; N];
+type Matrix = [[f32; N]; N];
+
+#[derive(Clone)]
+pub struct Ekf {
+
+    s: Vector,
+    p: Matrix
+}
+
+impl Ekf {
+
+    // Propagates the state forward by dt seconds, given accelerometer readings
+    // in g, in the body frame, and the current attitude
+    pub fn predict(&mut self, dt: f32, accel: [f32; 3], vstate: &VehicleState) {
+
+        let r = rotation(vstate);
+
+        // Body to world, minus gravity
+        let mut a = [0.0; 3];
+
+        for (i, ai) in a.iter_mut().enumerate() {
+            *ai = GRAVITY * (r[i][0] * accel[0] + r[i][1] * accel[1] + r[i][2] * accel[2]);
+        }
+
+        a[2] -= GRAVITY;
+
+        let q_pos = sq(ACCEL_STDEV) * sq(dt * dt) / 4.0;
+        let q_cross = sq(ACCEL_STDEV) * dt * dt * dt / 2.0;
+        let q_vel = sq(ACCEL_STDEV) * dt * dt;
+
+        for (axis, pos) in [X, Y, Z].into_iter().enumerate() {
+
+            let vel = pos + 1;
+
+            self.s[pos] += self.s[vel] * dt + 0.5 * a[axis] * dt * dt;
+            self.s[vel] += a[axis] * dt;
+
+            // P = F P F', where F is the identity plus dt at (pos, vel)
+            for i in 0..N {
+                self.p[i][pos] += dt * self.p[i][vel];
+            }
+            for j in 0..N {
+                self.p[pos][j] += dt * self.p[vel][j];
+            }
+
+            self.p[pos][pos] += q_pos;
+            self.p[pos][vel] += q_cross;
+            self.p[vel][pos] += q_cross;
+            self.p[vel][vel] += q_vel;
+        }
+    }
+
+    // Altitude in meters above the takeoff point
+    pub fn update_baro(&mut self, altitude: f32) {
+
+        let mut h = [0.0; N];
+
+        h[Z] = 1.0;
+
+        self.scalar_update(&h, altitude - self.s[Z], sq(BARO_STDEV));
+    }
+
+    // Distance in meters along the body's downward axis
+    pub fn update_range(&mut self, range: f32, vstate: &VehicleState) {
+
+        let r22 = rotation(vstate)[2][2];
+
+        if range <= 0.0 || range > RANGE_MAX || r22 < TILT_COS_MIN {
+            return;
+        }
+
+        let mut h = [0.0; N];
+
+        h[Z] = 1.0 / r22;
+
+        self.scalar_update(&h, range - self.s[Z] / r22, sq(RANGE_STDEV));
+    }
+
+    // Pixel counts accumulated by a downward-facing optical-flow sensor over
+    // dt seconds, with body rates (degrees per second) from the gyro
+    pub fn update_flow(&mut self, dpixel_x: f32, dpixel_y: f32, dt: f32, vstate: &VehicleState) {
+
+        let r22 = rotation(vstate)[2][2];
+
+        if r22 < TILT_COS_MIN {
+            return;
+        }
+
+        let z = self.s[Z].max(FLOW_HEIGHT_MIN);
+
+        let (spsi, cpsi) = sin_cos(vstate.psi);
+
+        // Horizontal velocity in the body frame
+        let vx = cpsi * self.s[DX] + spsi * self.s[DY];
+        let vy = -spsi * self.s[DX] + cpsi * self.s[DY];
+
+        let c = dt * FLOW_NPIX / FLOW_THETAPIX;
+
+        let k = c * r22 / z;
+
+        let mut h = [0.0; N];
+
+        h[DX] = k * cpsi;
+        h[DY] = k * spsi;
+        h[Z] = if self.s[Z] > FLOW_HEIGHT_MIN { -k * vx / z } else { 0.0 };
+
+        let predicted = k * vx - c * deg2rad(vstate.dtheta);
+
+        self.scalar_update(&h, dpixel_x - predicted, sq(FLOW_STDEV));
+
+        let mut h = [0.0; N];
+
+        h[DX] = -k * spsi;
+        h[DY] = k * cpsi;
+        h[Z] = if self.s[Z] > FLOW_HEIGHT_MIN { -k * vy / z } else { 0.0 };
+
+        let predicted = k * vy + c * deg2rad(vstate.dphi);
+
+        self.scalar_update(&h, dpixel_y - predicted, sq(FLOW_STDEV));
+    }
+
+    pub fn set_vehicle_state(&self, vstate: &mut VehicleState) {
+
+        vstate.x = self.s[X];
+        vstate.dx = self.s[DX];
+        vstate.y = self.s[Y];
+        vstate.dy = self.s[DY];
+        vstate.z = self.s[Z];
+        vstate.dz = self.s[DZ];
+    }
+
+    fn scalar_update(&mut self, h: &Vector, innovation: f32, r: f32) {
+
+        // P h'
+        let mut ph = [0.0; N];
+
+        for (i, phi) in ph.iter_mut().enumerate() {
+            *phi = (0..N).map(|j| self.p[i][j] * h[j]).sum();
+        }
+
+        let s = (0..N).map(|i| h[i] * ph[i]).sum::<f32>() + r;
+
+        let k = ph.map(|v| v / s);
+
+        for (si, ki) in self.s.iter_mut().zip(k) {
+            *si += ki * innovation;
+        }
+
+        // P = P - K h P, kept symmetric
+        for (row, ki) in self.p.iter_mut().zip(k) {
+            for (pij, phj) in row.iter_mut().zip(ph) {
+                *pij -= ki * phj;
+            }
+        }
+
+        for i in 0..N {
+            for j in 0..i {
+                let v = 0.5 * (self.p[i][j] + self.p[j][i]);
+                self.p[i][j] = v;
+                self.p[j][i] = v;
+            }
+        }
+    }
+}
+
+pub fn make() -> Ekf {
+
+    let mut p = [[0.0; N]; N];
+
+    for pos in [X, Y, Z] {
+        p[pos][pos] = sq(POSITION_STDEV_INITIAL);
+        p[pos + 1][pos + 1] = sq(VELOCITY_STDEV_INITIAL);
+    }
+
+    Ekf { s: [0.0; N], p }
+}
+
+// Center-zone distance in meters from a VL53L5 4x4 range image in
+// millimeters, ignoring invalid (non-positive) zones
+pub fn vl53l5_range(zones: &[i16; 16]) -> f32 {
+
+    let center = [zones[5], zones[6], zones[9], zones[10]];
+
+    let valid = center.iter().filter(|&&mm| mm > 0);
+
+    let count = valid.clone().count();
+
+    if count > 0 {
+        valid.map(|&mm| mm as f32).sum::<f32>() / (count as f32) / 1000.0
+    } else {
+        0.0
+    }
+}
+
+// Body-to-world rotation matrix from Euler angles in degrees
+fn rotation(vstate: &VehicleState) -> [[f32; 3]; 3] {
+
+    let (sphi, cphi) = sin_cos(vstate.phi);
+    let (stheta, ctheta) = sin_cos(vstate.theta);
+    let (spsi, cpsi) = sin_cos(vstate.psi);
+
+    [
+        [cpsi * ctheta, cpsi * stheta * sphi - spsi * cphi, cpsi * stheta * cphi + spsi * sphi],
+        [spsi * ctheta, spsi * stheta * sphi + cpsi * cphi, spsi * stheta * cphi - cpsi * sphi],
+        [-stheta, ctheta * sphi, ctheta * cphi]
+    ]
+}
+
+// Sine and cosine of an angle in degrees
+fn sin_cos(deg: f32) -> (f32, f32) {
+
+    let rad = deg2rad(deg);
+
+    (math::sin(rad), math::cos(rad))
+}
+
+fn sq(x: f32) -> f32 {
+
+    x * x
+}
@@ -0,0 +1,218 @@
+/*
+   Failsafe handling of receiver signal loss, after Betaflight's failsafe.c
+
+   Once the receiver has had a signal, losing it starts a guard period in
+   which the last stick demands are held, so that brief dropouts go
+   unnoticed.  If the signal stays lost, the vehicle levels and descends for
+   a fixed time, then lands (disarms).  The pilot regains control once the
+   signal has been back for a recovery period.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::Demands;
+use crate::pids;
+use crate::pids::AltHoldConfig;
+use crate::pids::InvalidConfig;
+use crate::pids::check;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Config {
+
+    // How long to hold the last stick demands after losing the signal
+    pub guard_usec: u32,
+
+    // How long to descend before disarming
+    pub landing_usec: u32,
+
+    // How long the signal must be back before the pilot regains control
+    pub recovery_usec: u32,
+
+    // Throttle demand for descending without an altitude estimate
+    pub descent_throttle: f32,
+
+    // Descent rate in meters per second, when an altitude-hold controller
+    // can be used
+    pub descent_velocity: f32
+}
+
+pub const DEFAULT_CONFIG: Config = Config {
+    guard_usec: 1_000_000,
+    landing_usec: 10_000_000,
+    recovery_usec: 1_000_000,
+    descent_throttle: 0.3,
+    descent_velocity: 1.0
+};
+
+impl Config {
+
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+
+        check(self.landing_usec > 0, "landing_usec")?;
+        check((0.0..=1.0).contains(&self.descent_throttle), "descent_throttle")?;
+
+        // Negative would climb instead
+        check(self.descent_velocity > 0.0, "descent_velocity")
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Phase {
+
+    // Pilot in control
+    Idle,
+
+    // Signal lost; holding the last stick demands
+    GuardTime,
+
+    // Signal lost; level and descending
+    Landing,
+
+    // Landing time is up; the vehicle should be disarmed
+    Landed
+}
+
+#[derive(Clone)]
+pub struct Failsafe {
+
+    config: Config,
+    phase: Phase,
+    had_signal: bool,
+
+    // When the current phase started
+    phase_usec: u32,
+
+    // When the signal came back, during failsafe
+    signal_usec: Option<u32>
+}
+
+impl Failsafe {
+
+    // Advances the state machine, given whether the receiver has a signal
+    // (e.g. from Receiver::have_signal())
+    pub fn update(&mut self, usec: u32, have_signal: bool) -> Phase {
+
+        self.had_signal |= have_signal;
+
+        if !self.had_signal {
+            return self.phase;
+        }
+
+        let elapsed = usec.wrapping_sub(self.phase_usec);
+
+        self.signal_usec = match (have_signal, self.signal_usec) {
+            (false, _) => None,
+            (true, None) => Some(usec),
+            (true, since) => since
+        };
+
+        let recovered = self.signal_usec
+            .map(|since| usec.wrapping_sub(since) >= self.config.recovery_usec)
+            .unwrap_or(false);
+
+        let next = match self.phase {
+
+            Phase::Idle => if have_signal { Phase::Idle } else { Phase::GuardTime },
+
+            // A brief dropout ends as soon as the signal is back
+            Phase::GuardTime => {
+                if have_signal {
+                    Phase::Idle
+                } else if elapsed >= self.config.guard_usec {
+                    Phase::Landing
+                } else {
+                    Phase::GuardTime
+                }
+            }
+
+            Phase::Landing => {
+                if recovered {
+                    Phase::Idle
+                } else if elapsed >= self.config.landing_usec {
+                    Phase::Landed
+                } else {
+                    Phase::Landing
+                }
+            }
+
+            // The vehicle stays disarmed, but can be re-armed once recovered
+            Phase::Landed => if recovered { Phase::Idle } else { Phase::Landed }
+        };
+
+        if next != self.phase {
+            self.phase = next;
+            self.phase_usec = usec;
+        }
+
+        self.phase
+    }
+
+    pub fn phase(&self) -> Phase {
+
+        self.phase
+    }
+
+    pub fn is_active(&self) -> bool {
+
+        self.phase != Phase::Idle
+    }
+
+    pub fn should_disarm(&self) -> bool {
+
+        self.phase == Phase::Landed
+    }
+
+    // Stick demands to use in the current phase.  With a valid altitude
+    // estimate, the landing throttle is a climb-rate demand for the
+    // altitude-hold controller in the PID chain, configured by alt_hold;
+    // otherwise it is the descent throttle itself.
+    pub fn get_demands(
+        &self,
+        stick_demands: &Demands,
+        alt_hold: &AltHoldConfig,
+        altitude_valid: bool) -> Demands {
+
+        let level = |throttle| Demands { throttle, roll: 0.0, pitch: 0.0, yaw: 0.0 };
+
+        match self.phase {
+
+            Phase::Idle | Phase::GuardTime => stick_demands.clone(),
+
+            Phase::Landing => level(if altitude_valid {
+                pids::alt_hold_throttle(alt_hold, -self.config.descent_velocity)
+            } else {
+                self.config.descent_throttle
+            }),
+
+            Phase::Landed => level(0.0)
+        }
+    }
+}
+
+pub fn make(config: Config) -> Result<Failsafe, InvalidConfig> {
+
+    config.validate()?;
+
+    Ok(Failsafe {
+        config,
+        phase: Phase::Idle,
+        had_signal: false,
+        phase_usec: 0,
+        signal_usec: None
+    })
+}
@@ -0,0 +1,249 @@
+/*
+   C ABI for the Hackflight core, so that the Arduino sketches can link the
+   Rust library in place of the C++ one
+
+   Controllers are kept in a fixed-size static pool, since there is no
+   allocator.  Nothing guards the pool, so the functions are unsafe: as with
+   the C++ firmware, all calls must come from a single thread, and none may
+   interrupt another.  The matching C header is generated into include/hackflight.h
+   when building with the ffi feature.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use core::cell::UnsafeCell;
+
+use crate::Demands;
+use crate::Motors;
+use crate::VehicleState;
+use crate::clock::LoopConfig;
+use crate::mixers::MixerType;
+use crate::mixers::saturation;
+use crate::mixers::saturation::SaturationConfig;
+use crate::output;
+use crate::output::Commands;
+use crate::output::Output;
+use crate::output::OutputConfig;
+use crate::pids;
+
+pub const MAX_CONTROLLERS: usize = 4;
+
+struct State {
+
+    pids: [Option<pids::Controller>; MAX_CONTROLLERS],
+    count: usize,
+
+    mixer: MixerType,
+    saturation: SaturationConfig,
+
+    output: Output
+}
+
+impl State {
+
+    fn add(&mut self, pid: pids::Controller) -> bool {
+
+        if self.count == MAX_CONTROLLERS {
+            return false;
+        }
+
+        self.pids[self.count] = Some(pid);
+        self.count += 1;
+
+        true
+    }
+}
+
+struct Pool(UnsafeCell<State>);
+
+// Safe only because the hf_ functions require that the firmware calls in
+// from a single thread
+unsafe impl Sync for Pool {}
+
+static POOL: Pool = Pool(UnsafeCell::new(State {
+    pids: [const { None }; MAX_CONTROLLERS],
+    count: 0,
+    mixer: MixerType::QuadXbf,
+    saturation: saturation::DEFAULT_CONFIG,
+    output: output::DEFAULT_OUTPUT
+}));
+
+// Callers must not hold on to the result past their own return, so that
+// no two references are ever live at once
+unsafe fn state() -> &'static mut State {
+
+    &mut *POOL.0.get()
+}
+
+/// Removes all controllers and goes back to the quad-X (Betaflight ordering)
+/// mixer, with the default saturation handling and motor output
+///
+/// # Safety
+///
+/// Not reentrant: no other hf_ function may be running, e.g. on another
+/// thread or in an interrupt handler.
+#[no_mangle]
+pub unsafe extern "C" fn hf_reset() {
+
+    let state = state();
+
+    state.pids = [const { None }; MAX_CONTROLLERS];
+    state.count = 0;
+    state.mixer = MixerType::QuadXbf;
+    state.saturation = saturation::DEFAULT_CONFIG;
+    state.output = output::DEFAULT_OUTPUT;
+}
+
+/// Chooses the frame that hf_step() mixes for
+///
+/// # Safety
+///
+/// Not reentrant: no other hf_ function may be running, e.g. on another
+/// thread or in an interrupt handler.
+#[no_mangle]
+pub unsafe extern "C" fn hf_set_mixer(mixer: MixerType) {
+
+    state().mixer = mixer;
+}
+
+/// Chooses how hf_step() limits the motors when the demands saturate them
+///
+/// # Safety
+///
+/// Not reentrant: no other hf_ function may be running, e.g. on another
+/// thread or in an interrupt handler.
+#[no_mangle]
+pub unsafe extern "C" fn hf_set_saturation(config: SaturationConfig) {
+
+    state().saturation = config;
+}
+
+/// Chooses how hf_output() turns motor values into ESC commands; returns
+/// false, keeping the previous choice, if the configuration is invalid
+///
+/// # Safety
+///
+/// Not reentrant: no other hf_ function may be running, e.g. on another
+/// thread or in an interrupt handler.
+#[no_mangle]
+pub unsafe extern "C" fn hf_set_output(config: OutputConfig) -> bool {
+
+    output::make(config)
+        .map(|output| state().output = output)
+        .is_ok()
+}
+
+/// Adds an angle (level) PID controller running at the given loop rate, with
+/// the default tuning; returns false if there is no room left, the level
+/// gain isn't positive, or the loop rate is too low for the default filters
+///
+/// # Safety
+///
+/// Not reentrant: no other hf_ function may be running, e.g. on another
+/// thread or in an interrupt handler.
+#[no_mangle]
+pub unsafe extern "C" fn hf_add_angle_pid(
+    k_rate_p: f32,
+    k_rate_i: f32,
+    k_rate_d: f32,
+    k_rate_f: f32,
+    k_level_p: f32,
+    rate_hz: u32) -> bool {
+
+    let loop_config = LoopConfig { rate_hz };
+
+    pids::make_angle(
+        k_rate_p,
+        k_rate_i,
+        k_rate_d,
+        k_rate_f,
+        k_level_p,
+        &loop_config,
+        &pids::DEFAULT_ANGLE_CONFIG)
+        .map(|pid| state().add(pid))
+        .unwrap_or(false)
+}
+
+/// Adds an altitude-hold PID controller; returns false if there is no room
+/// left
+///
+/// # Safety
+///
+/// Not reentrant: no other hf_ function may be running, e.g. on another
+/// thread or in an interrupt handler.
+#[no_mangle]
+pub unsafe extern "C" fn hf_add_alt_hold_pid(k_p: f32, k_i: f32) -> bool {
+
+    pids::make_alt_hold(k_p, k_i, &pids::DEFAULT_ALT_HOLD_CONFIG)
+        .map(|pid| state().add(pid))
+        .unwrap_or(false)
+}
+
+/// As crate::step(), running the controllers in the order they were added
+/// and mixing for the frame chosen by hf_set_mixer(), limited as chosen by
+/// hf_set_saturation()
+///
+/// # Safety
+///
+/// Not reentrant: no other hf_ function may be running, e.g. on another
+/// thread or in an interrupt handler.  The pointers must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn hf_step(
+    stick_demands: &Demands,
+    vstate: &VehicleState,
+    pid_reset: bool,
+    armed: bool,
+    usec: u32,
+    motors: &mut Motors) {
+
+    let state = state();
+
+    *motors = crate::step(
+        stick_demands,
+        vstate,
+        state.pids.iter_mut().flatten(),
+        &pid_reset,
+        &armed,
+        &usec,
+        &state.mixer.mixer(&state.saturation));
+}
+
+/// ESC commands for the motor values from hf_step(), as chosen by
+/// hf_set_output(); the pack voltage is zero when not measured
+///
+/// # Safety
+///
+/// Not reentrant: no other hf_ function may be running, e.g. on another
+/// thread or in an interrupt handler.  The pointers must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn hf_output(
+    motors: &Motors,
+    armed: bool,
+    voltage: f32,
+    commands: &mut Commands) {
+
+    *commands = state().output.get_commands(motors, armed, Some(voltage));
+}
+
+// Only on bare-metal targets, since anything linking the standard library
+// (e.g. the tests) brings its own
+#[cfg(all(feature = "panic-halt", target_os = "none"))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+
+    loop { }
+}
@@ -16,112 +16,289 @@
    Hackflight. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::f32::consts::PI;
+use core::f32::consts::PI;
 
-use crate::clock::DT;
+use crate::math;
+
+pub mod dyn_notch;
 
 // Pt1 --------------------------------------------------------------------
 
-#[derive(Clone,Copy)]
+#[derive(Clone)]
 pub struct Pt1 {
 
     state: f32,
-    k: f32
+    k: f32,
+    dt: f32
 }
 
-pub fn apply_pt1(mut filter: Pt1, input: f32) -> f32 {
+impl Pt1 {
 
-    filter.state = filter.state + filter.k * (input - filter.state);
+    pub fn apply(&mut self, input: f32) -> f32 {
 
-    filter.state
-}
+        self.state += self.k * (input - self.state);
 
-pub fn make_pt1(f_cut: f32) -> Pt1 {
+        self.state
+    }
 
-    let k = compute_pt1_gain(f_cut);
+    pub fn set_cutoff(&mut self, f_cut: f32) {
 
-    Pt1 {state: 0.0, k: k }
+        self.k = compute_pt1_gain(f_cut, self.dt);
+    }
 }
 
-pub fn adjust_pt1_gain(mut filter: Pt1, f_cut: f32)
-{
-    filter.k = compute_pt1_gain(f_cut);
-}
+pub fn make_pt1(f_cut: f32, dt: f32) -> Pt1 {
 
+    Pt1 {state: 0.0, k: compute_pt1_gain(f_cut, dt), dt }
+}
 
-fn compute_pt1_gain(f_cut:f32) -> f32 {
+fn compute_pt1_gain(f_cut:f32, dt: f32) -> f32 {
 
-    compute_gain(1.0, f_cut)
+    compute_gain(1.0, f_cut, dt)
 }
 
 // Pt2 --------------------------------------------------------------------
 
-#[derive(Clone,Copy)]
+#[derive(Clone)]
 pub struct Pt2 {
 
     state: f32,
     state1: f32,
-    k: f32
+    k: f32,
+    dt: f32
 }
 
-pub fn apply_pt2(mut filter: Pt2, input: f32) -> f32 {
+impl Pt2 {
 
-    let state1 = filter.state1 + filter.k * (input - filter.state1);
+    pub fn apply(&mut self, input: f32) -> f32 {
 
-    filter.state = filter.state + filter.k * (state1 - filter.state);
+        self.state1 += self.k * (input - self.state1);
 
-    filter.state
-}
+        self.state += self.k * (self.state1 - self.state);
+
+        self.state
+    }
 
-pub fn make_pt2(f_cut: f32) -> Pt2 {
+    pub fn set_cutoff(&mut self, f_cut: f32) {
+
+        self.k = compute_gain_with_order(2.0, f_cut, self.dt);
+    }
+}
 
-    let k = compute_gain_with_order(2.0, f_cut);
+pub fn make_pt2(f_cut: f32, dt: f32) -> Pt2 {
 
-    Pt2 {state: 0.0, state1: 0.0, k: k }
+    Pt2 {state: 0.0, state1: 0.0, k: compute_gain_with_order(2.0, f_cut, dt), dt }
 }
 
 // Pt3 --------------------------------------------------------------------
 
-#[derive(Clone,Copy)]
+#[derive(Clone)]
 pub struct Pt3 {
 
     state: f32,
     state1: f32,
     state2: f32,
-    k: f32
+    k: f32,
+    dt: f32
 }
 
-pub fn apply_pt3(mut filter: Pt3, input: f32) -> f32 {
+impl Pt3 {
 
-    let state1 = filter.state1 + filter.k * (input - filter.state1);
-    let state2 = filter.state2 + filter.k * (state1 - filter.state2);
+    pub fn apply(&mut self, input: f32) -> f32 {
 
-    filter.state = filter.state + filter.k * (state2 - filter.state);
+        self.state1 += self.k * (input - self.state1);
+        self.state2 += self.k * (self.state1 - self.state2);
 
-    filter.state
-}
+        self.state += self.k * (self.state2 - self.state);
+
+        self.state
+    }
 
-pub fn make_pt3(f_cut: f32) -> Pt3 {
+    pub fn set_cutoff(&mut self, f_cut: f32) {
+
+        self.k = compute_gain_with_order(3.0, f_cut, self.dt);
+    }
+}
 
-    let k = compute_gain_with_order(3.0, f_cut);
+pub fn make_pt3(f_cut: f32, dt: f32) -> Pt3 {
 
-    Pt3 {state: 0.0, state1: 0.0, state2: 0.0, k: k }
+    Pt3 {state: 0.0, state1: 0.0, state2: 0.0, k: compute_gain_with_order(3.0, f_cut, dt), dt }
 }
 
 
 // Helpers --------------------------------------------------------------------
 
-fn compute_gain_with_order(order: f32, f_cut: f32) -> f32 {
+fn compute_gain_with_order(order: f32, f_cut: f32, dt: f32) -> f32 {
 
-    let two: f32 = 2.0;
-    let order_cutoff_correction = 1.0 / (two.powf(1.0 / order) - 1.0).sqrt();
+    let order_cutoff_correction = 1.0 / math::sqrt(math::powf(2.0, 1.0 / order) - 1.0);
 
-    compute_gain(order_cutoff_correction, f_cut)
+    compute_gain(order_cutoff_correction, f_cut, dt)
 }
 
-fn compute_gain(order_cutoff_correction: f32, f_cut: f32) -> f32 {
+fn compute_gain(order_cutoff_correction: f32, f_cut: f32, dt: f32) -> f32 {
 
     let rc = 1.0 / (2.0 * order_cutoff_correction * PI * f_cut);
 
-    DT / (rc + DT)
+    dt / (rc + dt)
+}
+
+// Biquad -----------------------------------------------------------------
+
+// Butterworth Q
+pub const BIQUAD_Q: f32 = core::f32::consts::FRAC_1_SQRT_2;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum BiquadType {
+
+    Lowpass,
+    Notch,
+    Bandpass
+}
+
+// Direct Form 1, so that coefficients can be changed while running without
+// transients
+#[derive(Clone)]
+pub struct Biquad {
+
+    kind: BiquadType,
+    q: f32,
+    dt: f32,
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32
+}
+
+impl Biquad {
+
+    pub fn apply(&mut self, input: f32) -> f32 {
+
+        let result = self.b0 * input + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1 - self.a2 * self.y2;
+
+        self.x2 = self.x1;
+        self.x1 = input;
+
+        self.y2 = self.y1;
+        self.y1 = result;
+
+        result
+    }
+
+    // Changes the cutoff (lowpass) or center (notch, bandpass) frequency,
+    // keeping the filter state
+    pub fn set_frequency(&mut self, freq: f32) {
+
+        let omega = 2.0 * PI * freq * self.dt;
+        let sn = math::sin(omega);
+        let cs = math::cos(omega);
+        let alpha = sn / (2.0 * self.q);
+
+        let (b0, b1, b2) = match self.kind {
+            BiquadType::Lowpass => ((1.0 - cs) / 2.0, 1.0 - cs, (1.0 - cs) / 2.0),
+            BiquadType::Notch => (1.0, -2.0 * cs, 1.0),
+            BiquadType::Bandpass => (alpha, 0.0, -alpha)
+        };
+
+        let a0 = 1.0 + alpha;
+
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = -2.0 * cs / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+}
+
+pub fn make_biquad(kind: BiquadType, freq: f32, q: f32, dt: f32) -> Biquad {
+
+    let mut filter = Biquad {
+        kind,
+        q,
+        dt,
+        b0: 0.0,
+        b1: 0.0,
+        b2: 0.0,
+        a1: 0.0,
+        a2: 0.0,
+        x1: 0.0,
+        x2: 0.0,
+        y1: 0.0,
+        y2: 0.0
+    };
+
+    filter.set_frequency(freq);
+
+    filter
+}
+
+pub fn make_biquad_lowpass(f_cut: f32, dt: f32) -> Biquad {
+
+    make_biquad(BiquadType::Lowpass, f_cut, BIQUAD_Q, dt)
+}
+
+// Notch specified Betaflight-style, by center and lower cutoff frequencies
+pub fn make_biquad_notch(f_center: f32, f_cutoff: f32, dt: f32) -> Biquad {
+
+    make_biquad(BiquadType::Notch, f_center, notch_q(f_center, f_cutoff), dt)
+}
+
+pub fn notch_q(f_center: f32, f_cutoff: f32) -> f32 {
+
+    f_center * f_cutoff / (f_center * f_center - f_cutoff * f_cutoff)
+}
+
+// Dynamic notch ----------------------------------------------------------
+
+// Notch whose center frequency follows a peak frequency supplied at runtime
+// (e.g., from a spectral estimator), constrained to a fixed band
+#[derive(Clone)]
+pub struct DynamicNotch {
+
+    notch: Biquad,
+    min_hz: f32,
+    max_hz: f32,
+    center_hz: f32
+}
+
+impl DynamicNotch {
+
+    pub fn apply(&mut self, input: f32) -> f32 {
+
+        self.notch.apply(input)
+    }
+
+    pub fn set_center(&mut self, f_center: f32) {
+
+        // Stay safely below Nyquist
+        let max_hz = self.max_hz.min(0.45 / self.notch.dt);
+
+        self.center_hz = f_center.max(self.min_hz).min(max_hz);
+
+        self.notch.set_frequency(self.center_hz);
+    }
+
+    pub fn center(&self) -> f32 {
+
+        self.center_hz
+    }
+}
+
+pub fn make_dynamic_notch(min_hz: f32, max_hz: f32, q: f32, dt: f32) -> DynamicNotch {
+
+    let mut filter = DynamicNotch {
+        notch: make_biquad(BiquadType::Notch, min_hz, q, dt),
+        min_hz,
+        max_hz,
+        center_hz: min_hz
+    };
+
+    filter.set_center(min_hz);
+
+    filter
 }
@@ -0,0 +1,343 @@
+/*
+   Dynamic notch filtering of gyro rates, after Betaflight's dyn_notch_filter.c
+
+   A sliding discrete Fourier transform (SDFT) of each (downsampled) gyro
+   axis is searched for up to three noise peaks, and a notch filter is steered
+   onto each peak.  All storage is fixed-size, so this works without an
+   allocator.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use core::f32::consts::PI;
+
+use crate::VehicleState;
+use crate::clock::LoopConfig;
+use crate::math;
+use crate::pids::InvalidConfig;
+use crate::pids::check;
+
+use super::DynamicNotch;
+use super::compute_gain;
+use super::make_dynamic_notch;
+
+pub const MAX_PEAKS: usize = 3;
+
+const SDFT_SAMPLE_SIZE: usize = 72;
+const SDFT_BIN_COUNT: usize = SDFT_SAMPLE_SIZE / 2;
+
+// Damping factor keeping the SDFT numerically stable
+const SDFT_R: f32 = 0.9999;
+
+// Peaks must stand this far above the mean power of the searched band
+const NOISE_THRESHOLD_FACTOR: f32 = 2.0;
+
+// Cutoff of the lowpass smoothing the notch center frequencies
+const CENTER_SMOOTHING_HZ: f32 = 4.0;
+
+#[derive(Clone)]
+struct Sdft {
+
+    samples: [f32; SDFT_SAMPLE_SIZE],
+    index: usize,
+    count: usize,
+    re: [f32; SDFT_BIN_COUNT],
+    im: [f32; SDFT_BIN_COUNT]
+}
+
+impl Sdft {
+
+    fn push(&mut self, sample: f32, twiddles: &Twiddles) {
+
+        let delta = sample - twiddles.r_power_n * self.samples[self.index];
+
+        self.samples[self.index] = sample;
+        self.index = (self.index + 1) % SDFT_SAMPLE_SIZE;
+        self.count = (self.count + 1).min(SDFT_SAMPLE_SIZE);
+
+        for k in 0..SDFT_BIN_COUNT {
+
+            let re = self.re[k] + delta;
+            let im = self.im[k];
+
+            self.re[k] = twiddles.re[k] * re - twiddles.im[k] * im;
+            self.im[k] = twiddles.re[k] * im + twiddles.im[k] * re;
+        }
+    }
+
+    // Squared magnitude of a bin, with a Hann window applied in the
+    // frequency domain
+    fn power(&self, k: usize) -> f32 {
+
+        let re = 0.5 * self.re[k] - 0.25 * (self.re[k - 1] + self.re[k + 1]);
+        let im = 0.5 * self.im[k] - 0.25 * (self.im[k - 1] + self.im[k + 1]);
+
+        re * re + im * im
+    }
+}
+
+fn make_sdft() -> Sdft {
+
+    Sdft {
+        samples: [0.0; SDFT_SAMPLE_SIZE],
+        index: 0,
+        count: 0,
+        re: [0.0; SDFT_BIN_COUNT],
+        im: [0.0; SDFT_BIN_COUNT]
+    }
+}
+
+#[derive(Clone)]
+struct Twiddles {
+
+    re: [f32; SDFT_BIN_COUNT],
+    im: [f32; SDFT_BIN_COUNT],
+    r_power_n: f32
+}
+
+fn make_twiddles() -> Twiddles {
+
+    let mut twiddles = Twiddles {
+        re: [0.0; SDFT_BIN_COUNT],
+        im: [0.0; SDFT_BIN_COUNT],
+        r_power_n: math::powf(SDFT_R, SDFT_SAMPLE_SIZE as f32)
+    };
+
+    for k in 0..SDFT_BIN_COUNT {
+
+        let phi = 2.0 * PI * (k as f32) / (SDFT_SAMPLE_SIZE as f32);
+
+        twiddles.re[k] = SDFT_R * math::cos(phi);
+        twiddles.im[k] = SDFT_R * math::sin(phi);
+    }
+
+    twiddles
+}
+
+#[derive(Clone)]
+pub struct DynNotch {
+
+    twiddles: Twiddles,
+    sdfts: [Sdft; 3],
+    notches: [[DynamicNotch; MAX_PEAKS]; 3],
+    active: [[bool; MAX_PEAKS]; 3],
+    centers: [[f32; MAX_PEAKS]; 3],
+    notch_count: usize,
+    min_hz: f32,
+    max_hz: f32,
+    sample_count: u32,
+    sample_index: u32,
+    accumulators: [f32; 3],
+    resolution_hz: f32,
+    start_bin: usize,
+    end_bin: usize,
+    smoothing_k: f32
+}
+
+impl DynNotch {
+
+    // Feeds the gyro rates of the vehicle state to the estimator and returns
+    // the state with those rates notch-filtered
+    pub fn apply(&mut self, vstate: &VehicleState) -> VehicleState {
+
+        let rates = [vstate.dphi, vstate.dtheta, vstate.dpsi];
+
+        for (accumulator, rate) in self.accumulators.iter_mut().zip(rates) {
+            *accumulator += rate;
+        }
+
+        self.sample_index += 1;
+
+        // Downsample by averaging, then update the spectra and notches
+        if self.sample_index == self.sample_count {
+
+            for axis in 0..3 {
+
+                let sample = self.accumulators[axis] / (self.sample_count as f32);
+
+                self.sdfts[axis].push(sample, &self.twiddles);
+
+                self.accumulators[axis] = 0.0;
+
+                self.update_peaks(axis);
+            }
+
+            self.sample_index = 0;
+        }
+
+        let mut filtered = rates;
+
+        for (axis, rate) in filtered.iter_mut().enumerate() {
+            for p in 0..self.notch_count {
+                if self.active[axis][p] {
+                    *rate = self.notches[axis][p].apply(*rate);
+                }
+            }
+        }
+
+        VehicleState {
+            dphi: filtered[0],
+            dtheta: filtered[1],
+            dpsi: filtered[2],
+            .. *vstate
+        }
+    }
+
+    // Peak frequencies currently being notched on an axis (0=roll,
+    // 1=pitch, 2=yaw), in increasing order
+    pub fn peaks(&self, axis: usize) -> [Option<f32>; MAX_PEAKS] {
+
+        let mut peaks = [None; MAX_PEAKS];
+
+        for (p, peak) in peaks.iter_mut().enumerate().take(self.notch_count) {
+            if self.active[axis][p] {
+                *peak = Some(self.centers[axis][p]);
+            }
+        }
+
+        peaks
+    }
+
+    fn update_peaks(&mut self, axis: usize) {
+
+        let sdft = &self.sdfts[axis];
+
+        // Spectrum of a partly-filled window is dominated by its start-up
+        // transient
+        if sdft.count < SDFT_SAMPLE_SIZE {
+            return;
+        }
+
+        let mut power = [0.0; SDFT_BIN_COUNT];
+
+        for (k, p) in power.iter_mut().enumerate().take(self.end_bin + 2).skip(self.start_bin - 1) {
+            *p = sdft.power(k);
+        }
+
+        let band = &power[self.start_bin..=self.end_bin];
+
+        let threshold = NOISE_THRESHOLD_FACTOR * band.iter().sum::<f32>() / (band.len() as f32);
+
+        // Find the strongest local maxima, strongest first
+        let mut bins = [0usize; MAX_PEAKS];
+        let mut found = 0;
+
+        for k in self.start_bin..=self.end_bin {
+
+            if power[k] <= threshold || power[k] <= power[k - 1] || power[k] < power[k + 1] {
+                continue;
+            }
+
+            let mut j = found.min(self.notch_count - 1);
+
+            if found == self.notch_count && power[k] <= power[bins[j]] {
+                continue;
+            }
+
+            while j > 0 && power[k] > power[bins[j - 1]] {
+                bins[j] = bins[j - 1];
+                j -= 1;
+            }
+
+            bins[j] = k;
+
+            found = (found + 1).min(self.notch_count);
+        }
+
+        // Assign peaks to notches in order of increasing frequency
+        bins[..found].sort_unstable();
+
+        for (p, &k) in bins[..found].iter().enumerate() {
+
+            // Parabolic interpolation between bins
+            let (y0, y1, y2) = (power[k - 1], power[k], power[k + 1]);
+            let denom = y0 - 2.0 * y1 + y2;
+            let offset = if denom != 0.0 { 0.5 * (y0 - y2) / denom } else { 0.0 };
+
+            let freq = (((k as f32) + offset) * self.resolution_hz)
+                .max(self.min_hz)
+                .min(self.max_hz);
+
+            let center = &mut self.centers[axis][p];
+
+            *center = if self.active[axis][p] {
+                *center + self.smoothing_k * (freq - *center)
+            } else {
+                freq
+            };
+
+            self.active[axis][p] = true;
+
+            self.notches[axis][p].set_center(*center);
+        }
+    }
+}
+
+// The band must lie below the Nyquist frequency of the loop
+pub fn make(
+    loop_config: &LoopConfig,
+    min_hz: f32,
+    max_hz: f32,
+    notch_count: usize,
+    q: f32) -> Result<DynNotch, InvalidConfig> {
+
+    loop_config.validate()?;
+
+    check(min_hz > 0.0, "min_hz")?;
+    check(max_hz > min_hz && max_hz <= (loop_config.rate_hz as f32) / 2.0, "max_hz")?;
+    check((1..=MAX_PEAKS).contains(&notch_count), "notch_count")?;
+    check(q > 0.0, "q")?;
+
+    let dt = loop_config.dt();
+
+    // Downsample so that the SDFT just covers the band of interest
+    let sample_count = (((loop_config.rate_hz as f32) / (2.0 * max_hz)) as u32).max(1);
+
+    let sdft_rate_hz = (loop_config.rate_hz as f32) / (sample_count as f32);
+
+    let resolution_hz = sdft_rate_hz / (SDFT_SAMPLE_SIZE as f32);
+
+    let start_bin = ((min_hz / resolution_hz) as usize).max(2);
+    let end_bin = (math::ceil(max_hz / resolution_hz) as usize).min(SDFT_BIN_COUNT - 3);
+
+    // A band squeezed into the top bins leaves none to search
+    check(start_bin <= end_bin, "min_hz")?;
+
+    let notch = make_dynamic_notch(min_hz, max_hz, q, dt);
+
+    Ok(DynNotch {
+        twiddles: make_twiddles(),
+        sdfts: [make_sdft(), make_sdft(), make_sdft()],
+        notches: [
+            [notch.clone(), notch.clone(), notch.clone()],
+            [notch.clone(), notch.clone(), notch.clone()],
+            [notch.clone(), notch.clone(), notch]
+        ],
+        active: [[false; MAX_PEAKS]; 3],
+        centers: [[min_hz; MAX_PEAKS]; 3],
+        notch_count,
+        min_hz,
+        max_hz,
+        sample_count,
+        sample_index: 0,
+        accumulators: [0.0; 3],
+        resolution_hz,
+        start_bin,
+        end_bin,
+        smoothing_k: compute_gain(1.0, CENTER_SMOOTHING_HZ, 1.0 / sdft_rate_hz)
+    })
+}
@@ -0,0 +1,435 @@
+/*
+   Hackflight IMU support: gyro calibration and filtering, plus Mahony
+   quaternion fusion of gyro and accelerometer (the C++ SoftQuatImu)
+
+   Copyright (C) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::VehicleState;
+use crate::clock::LoopConfig;
+use crate::filters;
+use crate::math;
+use crate::pids::InvalidConfig;
+use crate::utils::deg2rad;
+use crate::utils::rad2deg;
+
+const GYRO_CALIBRATION_DURATION_USEC: u32 = 1_250_000;
+const GYRO_LPF1_DYN_MIN_HZ: f32 = 250.0;
+const GYRO_LPF2_STATIC_HZ: f32 = 500.0;
+
+// Standard deviation (in raw counts) above which the vehicle is considered
+// to have moved during calibration
+const MOVEMENT_CALIBRATION_THRESHOLD: f32 = 48.0;
+
+// update_accelerometer() should be called at this rate
+pub const ACCEL_SAMPLE_RATE_HZ: u32 = 1000;
+const ACCEL_LPF_CUTOFF_HZ: f32 = 10.0;
+
+const MAHONY_KP: f32 = 30.0;
+const MAHONY_KI: f32 = 0.0;
+
+pub const DEFAULT_GYRO_SCALE_DPS: u16 = 2000;
+pub const DEFAULT_ACCEL_SCALE_G: u16 = 16;
+
+// Sensor-to-board alignment, as clockwise rotation about Z, optionally
+// flipped upside-down
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Rotation {
+
+    Cw0,
+    Cw90,
+    Cw180,
+    Cw270,
+    Cw0Flip,
+    Cw90Flip,
+    Cw180Flip,
+    Cw270Flip
+}
+
+fn rotate(rotation: Rotation, v: [f32; 3]) -> [f32; 3] {
+
+    let [x, y, z] = v;
+
+    match rotation {
+        Rotation::Cw0 => [x, y, z],
+        Rotation::Cw90 => [y, -x, z],
+        Rotation::Cw180 => [-x, -y, z],
+        Rotation::Cw270 => [-y, x, z],
+        Rotation::Cw0Flip => [-x, y, -z],
+        Rotation::Cw90Flip => [y, x, -z],
+        Rotation::Cw180Flip => [x, -y, -z],
+        Rotation::Cw270Flip => [-y, -x, -z]
+    }
+}
+
+// Running mean and variance (Welford)
+#[derive(Clone,Copy)]
+struct Stats {
+
+    old_m: f32,
+    new_m: f32,
+    old_s: f32,
+    new_s: f32,
+    n: u32
+}
+
+impl Stats {
+
+    fn clear(&mut self) {
+
+        self.n = 0;
+    }
+
+    fn push(&mut self, x: f32) {
+
+        self.n += 1;
+
+        if self.n == 1 {
+            self.old_m = x;
+            self.new_m = x;
+            self.old_s = 0.0;
+            self.new_s = 0.0;
+        } else {
+            self.new_m = self.old_m + (x - self.old_m) / (self.n as f32);
+            self.new_s = self.old_s + (x - self.old_m) * (x - self.new_m);
+            self.old_m = self.new_m;
+            self.old_s = self.new_s;
+        }
+    }
+
+    fn stdev(&self) -> f32 {
+
+        let variance = if self.n > 1 { self.new_s / ((self.n - 1) as f32) } else { 0.0 };
+
+        math::sqrt(variance)
+    }
+}
+
+fn make_stats() -> Stats {
+
+    Stats { old_m: 0.0, new_m: 0.0, old_s: 0.0, new_s: 0.0, n: 0 }
+}
+
+// Trapezoidal averaging of gyro rates between attitude updates
+#[derive(Clone,Copy)]
+struct GyroAccumulator {
+
+    sum: [f32; 3],
+    prev: [f32; 3],
+    count: u32
+}
+
+impl GyroAccumulator {
+
+    fn accumulate(&mut self, rates: [f32; 3]) {
+
+        for (k, rate) in rates.iter().enumerate() {
+            self.sum[k] += 0.5 * (self.prev[k] + rate);
+        }
+
+        self.prev = rates;
+
+        self.count += 1;
+    }
+
+    fn average(&self) -> [f32; 3] {
+
+        let n = self.count as f32;
+
+        if self.count > 0 { self.sum.map(|s| s / n) } else { [0.0; 3] }
+    }
+
+    fn reset(&mut self) {
+
+        self.sum = [0.0; 3];
+        self.count = 0;
+    }
+}
+
+#[derive(Clone,Copy,Debug)]
+struct Quaternion {
+
+    w: f32,
+    x: f32,
+    y: f32,
+    z: f32
+}
+
+#[derive(Clone)]
+pub struct Imu {
+
+    rotation: Rotation,
+    gyro_scale: f32,
+    accel_scale: f32,
+
+    calibration_cycles: i32,
+    calibration_cycles_remaining: i32,
+    calibration_sum: [f32; 3],
+    calibration_stats: [Stats; 3],
+    gyro_zero: [f32; 3],
+
+    gyro_lpf1: [filters::Pt1; 3],
+    gyro_lpf2: [filters::Pt1; 3],
+    gyro_filtered: [f32; 3],
+    gyro_accum: GyroAccumulator,
+
+    accel_lpf: [filters::Pt2; 3],
+    accel: [f32; 3],
+
+    quat: Quaternion,
+    integral: [f32; 3],
+
+    // None until the first attitude update
+    usec_prev: Option<u32>
+}
+
+impl Imu {
+
+    // Converts raw gyro counts to calibrated, filtered rates in degrees per
+    // second.  Call once per loop.
+    pub fn update_gyro(&mut self, raw_gyro: &[i16; 3], vstate: &mut VehicleState) {
+
+        let dps = if self.gyro_is_calibrating() {
+
+            self.calibrate_gyro(raw_gyro);
+
+            [0.0; 3]
+
+        } else {
+
+            let mut adc = [0.0; 3];
+
+            for k in 0..3 {
+                adc[k] = (raw_gyro[k] as f32) - self.gyro_zero[k];
+            }
+
+            rotate(self.rotation, adc).map(|v| v * self.gyro_scale)
+        };
+
+        for (k, rate) in dps.iter().enumerate() {
+            self.gyro_filtered[k] = self.gyro_lpf1[k].apply(self.gyro_lpf2[k].apply(*rate));
+        }
+
+        self.gyro_accum.accumulate(self.gyro_filtered);
+
+        vstate.dphi = self.gyro_filtered[0];
+        vstate.dtheta = self.gyro_filtered[1];
+        vstate.dpsi = self.gyro_filtered[2];
+    }
+
+    // Call at ACCEL_SAMPLE_RATE_HZ
+    pub fn update_accelerometer(&mut self, raw_accel: &[i16; 3]) {
+
+        let mut adc = [0.0; 3];
+
+        for k in 0..3 {
+            adc[k] = self.accel_lpf[k].apply(raw_accel[k] as f32) * self.accel_scale;
+        }
+
+        self.accel = rotate(self.rotation, adc);
+    }
+
+    // Fuses the gyro rates accumulated since the previous call with the
+    // latest accelerometer reading, yielding Euler angles in degrees.  The
+    // first call only starts the clock, having no interval to integrate over.
+    pub fn update_attitude(&mut self, usec: u32, vstate: &mut VehicleState) {
+
+        if let Some(usec_prev) = self.usec_prev {
+
+            let dt = (usec.wrapping_sub(usec_prev) as f32) * 1e-6;
+
+            self.mahony(dt, self.gyro_accum.average());
+        }
+
+        self.usec_prev = Some(usec);
+
+        self.gyro_accum.reset();
+
+        let (phi, theta, psi) = quat2euler(&self.quat);
+
+        vstate.phi = rad2deg(phi);
+        vstate.theta = rad2deg(theta);
+        vstate.psi = rad2deg(psi);
+    }
+
+    // Latest filtered accelerometer reading in g, for the position estimator
+    pub fn accel(&self) -> [f32; 3] {
+
+        self.accel
+    }
+
+    pub fn gyro_is_calibrating(&self) -> bool {
+
+        self.calibration_cycles_remaining > 0
+    }
+
+    pub fn recalibrate_gyro(&mut self) {
+
+        self.calibration_cycles_remaining = self.calibration_cycles;
+    }
+
+    fn calibrate_gyro(&mut self, raw_gyro: &[i16; 3]) {
+
+        // Reset at start of calibration
+        if self.calibration_cycles_remaining == self.calibration_cycles {
+            self.calibration_sum = [0.0; 3];
+            self.calibration_stats.iter_mut().for_each(|stats| stats.clear());
+            self.gyro_zero = [0.0; 3];
+        }
+
+        for (k, raw) in raw_gyro.iter().enumerate() {
+            self.calibration_sum[k] += *raw as f32;
+            self.calibration_stats[k].push(*raw as f32);
+        }
+
+        if self.calibration_cycles_remaining == 1 {
+
+            // Start over in case the vehicle was moved
+            if self.calibration_stats.iter().any(|stats|
+                stats.stdev() > MOVEMENT_CALIBRATION_THRESHOLD) {
+                self.recalibrate_gyro();
+                return;
+            }
+
+            let cycles = self.calibration_cycles as f32;
+
+            self.gyro_zero = self.calibration_sum.map(|sum| sum / cycles);
+        }
+
+        self.calibration_cycles_remaining -= 1;
+    }
+
+    // Adapted from
+    //  https://github.com/jremington/MPU-6050-Fusion/blob/main/MPU6050_MahonyIMU.ino
+    fn mahony(&mut self, dt: f32, gyro: [f32; 3]) {
+
+        let mut g = gyro.map(deg2rad);
+
+        let [mut ax, mut ay, mut az] = self.accel;
+
+        let Quaternion { w: qw, x: qx, y: qy, z: qz } = self.quat;
+
+        let acc_norm = ax * ax + ay * ay + az * az;
+
+        if acc_norm > 0.0 {
+
+            // Normalise accelerometer (assumed to measure the direction of
+            // gravity in body frame)
+            let recip_norm = 1.0 / math::sqrt(acc_norm);
+            ax *= recip_norm;
+            ay *= recip_norm;
+            az *= recip_norm;
+
+            // Estimated direction of gravity in the body frame (factor of
+            // two divided out)
+            let vx = qx * qz - qw * qy;
+            let vy = qw * qx + qy * qz;
+            let vz = qw * qw - 0.5 + qz * qz;
+
+            // Error is cross product between estimated and measured
+            // direction of gravity in body frame (half the actual
+            // magnitude)
+            let e = [ay * vz - az * vy, az * vx - ax * vz, ax * vy - ay * vx];
+
+            for k in 0..3 {
+
+                // Integral feedback, if enabled
+                if MAHONY_KI > 0.0 {
+                    self.integral[k] += MAHONY_KI * e[k] * dt;
+                    g[k] += self.integral[k];
+                }
+
+                // Proportional feedback
+                g[k] += MAHONY_KP * e[k];
+            }
+        }
+
+        // Integrate rate of change of quaternion, q cross gyro term
+        let [gx, gy, gz] = g.map(|v| v * 0.5 * dt);
+
+        let w = qw - qx * gx - qy * gy - qz * gz;
+        let x = qx + qw * gx + qy * gz - qz * gy;
+        let y = qy + qw * gy - qx * gz + qz * gx;
+        let z = qz + qw * gz + qx * gy - qy * gx;
+
+        let recip_norm = 1.0 / math::sqrt(w * w + x * x + y * y + z * z);
+
+        self.quat = Quaternion {
+            w: w * recip_norm,
+            x: x * recip_norm,
+            y: y * recip_norm,
+            z: z * recip_norm
+        };
+    }
+}
+
+// Returns roll, pitch, yaw in radians, with yaw in [0, 2pi)
+fn quat2euler(q: &Quaternion) -> (f32, f32, f32) {
+
+    let Quaternion { w: qw, x: qx, y: qy, z: qz } = *q;
+
+    let phi = math::atan2(2.0 * (qw * qx + qy * qz), qw * qw - qx * qx - qy * qy + qz * qz);
+
+    let theta = math::asin((2.0 * (qw * qy - qx * qz)).clamp(-1.0, 1.0));
+
+    let psi = math::atan2(2.0 * (qx * qy + qw * qz), qw * qw + qx * qx - qy * qy - qz * qz);
+
+    (phi, theta, if psi < 0.0 { psi + 2.0 * core::f32::consts::PI } else { psi })
+}
+
+pub fn make(
+    loop_config: &LoopConfig,
+    rotation: Rotation,
+    gyro_scale_dps: u16,
+    accel_scale_g: u16) -> Result<Imu, InvalidConfig> {
+
+    loop_config.validate()?;
+
+    let dt = loop_config.dt();
+
+    let accel_dt = 1.0 / (ACCEL_SAMPLE_RATE_HZ as f32);
+
+    let calibration_cycles =
+        ((GYRO_CALIBRATION_DURATION_USEC as f32) * 1e-6 * (loop_config.rate_hz as f32)) as i32;
+
+    let gyro_lpf1 = filters::make_pt1(GYRO_LPF1_DYN_MIN_HZ, dt);
+    let gyro_lpf2 = filters::make_pt1(GYRO_LPF2_STATIC_HZ, dt);
+    let accel_lpf = filters::make_pt2(ACCEL_LPF_CUTOFF_HZ, accel_dt);
+
+    Ok(Imu {
+        rotation,
+        gyro_scale: (gyro_scale_dps as f32) / 32768.0,
+        accel_scale: (accel_scale_g as f32) / 32768.0,
+        calibration_cycles,
+        calibration_cycles_remaining: calibration_cycles,
+        calibration_sum: [0.0; 3],
+        calibration_stats: [make_stats(); 3],
+        gyro_zero: [0.0; 3],
+        gyro_lpf1: [gyro_lpf1.clone(), gyro_lpf1.clone(), gyro_lpf1],
+        gyro_lpf2: [gyro_lpf2.clone(), gyro_lpf2.clone(), gyro_lpf2],
+        gyro_filtered: [0.0; 3],
+        gyro_accum: GyroAccumulator { sum: [0.0; 3], prev: [0.0; 3], count: 0 },
+        accel_lpf: [accel_lpf.clone(), accel_lpf.clone(), accel_lpf],
+        accel: [0.0; 3],
+
+        // Upright
+        quat: Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 },
+
+        integral: [0.0; 3],
+        usec_prev: None
+    })
+}
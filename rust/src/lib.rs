@@ -18,12 +18,31 @@
    Hackflight. If not, see <https://www.gnu.org/licenses/>.
  */
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
 pub mod pids;
 pub mod mixers;
 pub mod filters;
 pub mod clock;
 pub mod utils;
-
+mod math;
+pub mod imu;
+pub mod ekf;
+pub mod msp;
+pub mod receiver;
+pub mod failsafe;
+pub mod arming;
+pub mod modes;
+pub mod rates;
+pub mod output;
+
+#[cfg(feature = "serde")]
+pub mod config;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[repr(C)]
 #[derive(Clone)]
 pub struct Demands {
     pub throttle: f32,
@@ -32,6 +51,7 @@ pub struct Demands {
     pub yaw:      f32
 } 
 
+#[repr(C)]
 #[derive(Clone,Copy)]
 pub struct VehicleState {
 
@@ -49,35 +69,58 @@ pub struct VehicleState {
     pub dpsi:   f32
 }
 
+pub const MAX_MOTORS: usize = 8;
+
+// Only the first `count` values are used, in the mixer's motor order
+#[repr(C)]
+#[derive(Clone,Copy,Debug,PartialEq)]
 pub struct Motors {
 
-    pub m1: f32,
-    pub m2: f32,
-    pub m3: f32,
-    pub m4: f32
+    pub count: usize,
+    pub values: [f32; MAX_MOTORS],
+
+    // Yaw servo deflection in [-1,+1], for frames that have one
+    pub servo: f32
+}
+
+impl Motors {
+
+    pub fn as_slice(&self) -> &[f32] {
+
+        &self.values[..self.count]
+    }
 }
 
 pub trait Mixer {
 
     fn get_motors(&self, demands: & Demands) -> Motors;
+
+    fn motor_count(&self) -> usize;
 }
 
-// Corresponds to C++ Mixer::step()
-pub fn step(
+// Corresponds to C++ Mixer::step(); motors are zero, and the PID
+// controllers held in reset, while disarmed
+pub fn step<'a>(
     stick_demands: &Demands,
     state: &VehicleState,
-    arr: &mut [pids::Controller],
+    arr: impl IntoIterator<Item = &'a mut pids::Controller>,
     pid_reset: &bool,
+    armed: &bool,
     usec: & u32,
     mixer: &dyn Mixer) -> Motors {
 
         let mut demands = stick_demands.clone();
 
-        for pid in arr.iter_mut() {
-            demands = pids::update(&mut *pid, *usec, demands, *state, *pid_reset);
+        for pid in arr {
+            demands = pids::update(pid, *usec, demands, *state, *pid_reset || !*armed);
         }
 
-        mixer.get_motors(&demands)
+        if *armed { mixer.get_motors(&demands) } else { make_motors_off(mixer.motor_count()) }
+}
+
+pub fn make_motors_off(count: usize) -> Motors {
+
+    Motors { count, values: [0.0; MAX_MOTORS], servo: 0.0 }
 }
 
 
@@ -0,0 +1,58 @@
+/*
+   Floating-point functions that core lacks: from libm by default, or from std
+   when the std feature is enabled
+
+   Copyright (C) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#[cfg(feature = "std")]
+mod imp {
+
+    pub fn sqrt(x: f32) -> f32 { x.sqrt() }
+
+    pub fn sin(x: f32) -> f32 { x.sin() }
+
+    pub fn cos(x: f32) -> f32 { x.cos() }
+
+    pub fn asin(x: f32) -> f32 { x.asin() }
+
+    pub fn atan2(y: f32, x: f32) -> f32 { y.atan2(x) }
+
+    pub fn powf(x: f32, y: f32) -> f32 { x.powf(y) }
+
+    pub fn ceil(x: f32) -> f32 { x.ceil() }
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+
+    pub fn sqrt(x: f32) -> f32 { libm::sqrtf(x) }
+
+    pub fn sin(x: f32) -> f32 { libm::sinf(x) }
+
+    pub fn cos(x: f32) -> f32 { libm::cosf(x) }
+
+    pub fn asin(x: f32) -> f32 { libm::asinf(x) }
+
+    pub fn atan2(y: f32, x: f32) -> f32 { libm::atan2f(y, x) }
+
+    pub fn powf(x: f32, y: f32) -> f32 { libm::powf(x, y) }
+
+    pub fn ceil(x: f32) -> f32 { libm::ceilf(x) }
+}
+
+pub use imp::*;
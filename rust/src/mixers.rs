@@ -19,3 +19,169 @@
  */
 
 pub mod quadxbf;
+pub mod quadx;
+pub mod quadp;
+pub mod hexx;
+pub mod hexp;
+pub mod y6;
+pub mod octox;
+pub mod x8;
+pub mod tricopter;
+pub mod geometry;
+pub mod saturation;
+
+use crate::Demands;
+use crate::MAX_MOTORS;
+use crate::Mixer;
+use crate::Motors;
+use crate::mixers::saturation::SaturationConfig;
+
+// One motor's share of each demand, as in Betaflight's motorMixer_t
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct MotorMix {
+
+    pub throttle: f32,
+    pub roll: f32,
+    pub pitch: f32,
+    pub yaw: f32
+}
+
+// Mixes the demands by a table with a row per motor, without limiting the
+// motor values; see saturation::mix_limited()
+pub fn mix(table: &[MotorMix], demands: &Demands) -> Motors {
+
+    let mut motors = crate::make_motors_off(table.len());
+
+    for (value, row) in motors.values.iter_mut().zip(table) {
+
+        *value =
+            demands.throttle * row.throttle +
+            demands.roll * row.roll +
+            demands.pitch * row.pitch +
+            demands.yaw * row.yaw;
+    }
+
+    motors
+}
+
+// A mixer for any frame, from its table
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct MatrixMixer {
+
+    table: [MotorMix; MAX_MOTORS],
+    count: usize,
+    saturation: SaturationConfig
+}
+
+impl MatrixMixer {
+
+    pub fn table(&self) -> &[MotorMix] {
+
+        &self.table[..self.count]
+    }
+
+    pub fn set_saturation(&mut self, config: &SaturationConfig) {
+
+        self.saturation = *config;
+    }
+}
+
+impl Mixer for MatrixMixer {
+
+    fn get_motors(&self, demands: &Demands) -> Motors {
+
+        saturation::mix_limited(self.table(), demands, &self.saturation)
+    }
+
+    fn motor_count(&self) -> usize {
+
+        self.count
+    }
+}
+
+// Returns None for an empty table or more than MAX_MOTORS rows
+pub fn make_matrix(table: &[MotorMix]) -> Option<MatrixMixer> {
+
+    if table.is_empty() || table.len() > MAX_MOTORS {
+        return None;
+    }
+
+    let mut mixer = MatrixMixer {
+        table: [MotorMix { throttle: 0.0, roll: 0.0, pitch: 0.0, yaw: 0.0 }; MAX_MOTORS],
+        count: table.len(),
+        saturation: saturation::DEFAULT_CONFIG
+    };
+
+    mixer.table[..table.len()].copy_from_slice(table);
+
+    Some(mixer)
+}
+
+// Mixers that can be chosen at runtime, e.g. from a saved configuration;
+// new ones go at the end, so that saved configurations keep their meaning
+#[repr(C)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MixerType {
+
+    QuadXbf,
+    QuadX,
+    QuadP,
+    HexX,
+    HexP,
+    Y6,
+    OctoX,
+    X8,
+    Tricopter
+}
+
+impl MixerType {
+
+    pub fn mixer(&self, saturation: &SaturationConfig) -> Preset {
+
+        Preset { mixer_type: *self, saturation: *saturation }
+    }
+}
+
+// A preset chosen at runtime, limiting the motors as its saturation config
+// says
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Preset {
+
+    pub mixer_type: MixerType,
+
+    pub saturation: SaturationConfig
+}
+
+impl Preset {
+
+    fn with<R>(&self, f: impl FnOnce(&dyn Mixer) -> R) -> R {
+
+        let saturation = self.saturation;
+
+        match self.mixer_type {
+            MixerType::QuadXbf => f(&quadxbf::QuadXbf { saturation }),
+            MixerType::QuadX => f(&quadx::QuadX { saturation }),
+            MixerType::QuadP => f(&quadp::QuadP { saturation }),
+            MixerType::HexX => f(&hexx::HexX { saturation }),
+            MixerType::HexP => f(&hexp::HexP { saturation }),
+            MixerType::Y6 => f(&y6::Y6 { saturation }),
+            MixerType::OctoX => f(&octox::OctoX { saturation }),
+            MixerType::X8 => f(&x8::X8 { saturation }),
+            MixerType::Tricopter => f(&tricopter::Tricopter { saturation })
+        }
+    }
+}
+
+impl Mixer for Preset {
+
+    fn get_motors(&self, demands: &Demands) -> Motors {
+
+        self.with(|mixer| mixer.get_motors(demands))
+    }
+
+    fn motor_count(&self) -> usize {
+
+        self.with(|mixer| mixer.motor_count())
+    }
+}
@@ -0,0 +1,169 @@
+/*
+   Mixer values computed from where the motors are and which way they spin,
+   for frames without a preset (stretched-X, dead-cat, asymmetric)
+
+   Each motor's thrust and reaction torque give its share of the vehicle's
+   thrust and roll, pitch and yaw torque.  The mixer is the pseudo-inverse
+   of that map, so that each demand moves only its own axis.  As in PX4's
+   mixer generator, the throttle and yaw columns are then scaled to a
+   largest value of one, and roll and pitch together, so that both keep the
+   same authority per unit of demand.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::MAX_MOTORS;
+use crate::mixers::MatrixMixer;
+use crate::mixers::MotorMix;
+use crate::mixers::make_matrix;
+
+// Smallest pivot, relative to the largest entry, before the frame is taken
+// to have no authority over some axis
+const SINGULAR: f32 = 1e-5;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Rotor {
+
+    // Forward and right of the center of mass, in any unit
+    pub x: f32,
+    pub y: f32,
+
+    // Seen from above
+    pub clockwise: bool,
+
+    // Thrust and reaction torque per unit of motor demand, relative to the
+    // other motors
+    pub thrust: f32,
+    pub torque: f32
+}
+
+// A rotor like all the others
+pub fn make_rotor(x: f32, y: f32, clockwise: bool) -> Rotor {
+
+    Rotor { x, y, clockwise, thrust: 1.0, torque: 1.0 }
+}
+
+// Returns None for no rotors, more than MAX_MOTORS, or a frame that can't
+// control all of thrust, roll, pitch and yaw independently (e.g. all motors
+// in a line, or all spinning the same way)
+pub fn make(rotors: &[Rotor]) -> Option<MatrixMixer> {
+
+    if rotors.is_empty() || rotors.len() > MAX_MOTORS {
+        return None;
+    }
+
+    // Thrust, roll, pitch and yaw per unit of each motor's demand, with the
+    // signs of the presets: right motors roll left, rear motors pitch down,
+    // clockwise motors yaw right
+    let effect = |rotor: &Rotor| [
+        rotor.thrust,
+        -rotor.y * rotor.thrust,
+        -rotor.x * rotor.thrust,
+        if rotor.clockwise { rotor.torque } else { -rotor.torque }
+    ];
+
+    // Pseudo-inverse B'(BB')^-1, with B the 4xN effectiveness matrix
+    let mut bbt = [[0.0; 4]; 4];
+
+    for rotor in rotors {
+
+        let b = effect(rotor);
+
+        for (row, bi) in bbt.iter_mut().zip(b) {
+            for (entry, bj) in row.iter_mut().zip(b) {
+                *entry += bi * bj;
+            }
+        }
+    }
+
+    let inverse = invert(bbt)?;
+
+    let mut table = [MotorMix { throttle: 0.0, roll: 0.0, pitch: 0.0, yaw: 0.0 }; MAX_MOTORS];
+
+    for (row, rotor) in table.iter_mut().zip(rotors) {
+
+        let b = effect(rotor);
+
+        let column = |j: usize| (0..4).map(|k| b[k] * inverse[k][j]).sum::<f32>();
+
+        *row = MotorMix { throttle: column(0), roll: column(1), pitch: column(2), yaw: column(3) };
+    }
+
+    let table = &mut table[..rotors.len()];
+
+    let largest = |get: fn(&MotorMix) -> f32| {
+        table.iter().map(|row| get(row).abs()).fold(0.0, f32::max)
+    };
+
+    let throttle_scale = largest(|row| row.throttle);
+    let cyclic_scale = largest(|row| row.roll).max(largest(|row| row.pitch));
+    let yaw_scale = largest(|row| row.yaw);
+
+    for row in table.iter_mut() {
+        row.throttle /= throttle_scale;
+        row.roll /= cyclic_scale;
+        row.pitch /= cyclic_scale;
+        row.yaw /= yaw_scale;
+    }
+
+    make_matrix(table)
+}
+
+// Gauss-Jordan elimination with partial pivoting
+fn invert(mut a: [[f32; 4]; 4]) -> Option<[[f32; 4]; 4]> {
+
+    let mut inverse = [[0.0; 4]; 4];
+
+    for (k, row) in inverse.iter_mut().enumerate() {
+        row[k] = 1.0;
+    }
+
+    let largest = a.iter().flatten().map(|x| x.abs()).fold(0.0, f32::max);
+
+    for col in 0..4 {
+
+        let pivot = (col..4)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+            .unwrap_or(col);
+
+        if a[pivot][col].abs() <= SINGULAR * largest {
+            return None;
+        }
+
+        a.swap(col, pivot);
+        inverse.swap(col, pivot);
+
+        let scale = a[col][col];
+
+        for j in 0..4 {
+            a[col][j] /= scale;
+            inverse[col][j] /= scale;
+        }
+
+        for i in (0..4).filter(|&i| i != col) {
+
+            let factor = a[i][col];
+
+            for j in 0..4 {
+                a[i][j] -= factor * a[col][j];
+                inverse[i][j] -= factor * inverse[col][j];
+            }
+        }
+    }
+
+    Some(inverse)
+}
@@ -0,0 +1,71 @@
+/*
+    Mixer values for hex-X frames using Betaflight motor layout:
+
+       4cw   2ccw
+          \ /
+   6ccw ---^--- 5cw
+          / \
+       3cw   1ccw
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::Demands;
+use crate::Mixer;
+use crate::Motors;
+use crate::mixers::MotorMix;
+use crate::mixers::saturation::SaturationConfig;
+use crate::mixers::saturation::mix_limited;
+
+pub const MOTORS: [MotorMix; 6] = [
+
+    // right rear
+    MotorMix { throttle: 1.0, roll: -0.5, pitch: 0.866025, yaw: -1.0 },
+
+    // right front
+    MotorMix { throttle: 1.0, roll: -0.5, pitch: -0.866025, yaw: -1.0 },
+
+    // left rear
+    MotorMix { throttle: 1.0, roll: 0.5, pitch: 0.866025, yaw: 1.0 },
+
+    // left front
+    MotorMix { throttle: 1.0, roll: 0.5, pitch: -0.866025, yaw: 1.0 },
+
+    // right
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: 0.0, yaw: 1.0 },
+
+    // left
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: 0.0, yaw: -1.0 }
+];
+
+pub struct HexX {
+
+    pub saturation: SaturationConfig
+}
+
+impl Mixer for HexX {
+
+    fn get_motors(&self, demands: &Demands) -> Motors {
+
+        mix_limited(&MOTORS, demands, &self.saturation)
+    }
+
+    fn motor_count(&self) -> usize {
+
+        MOTORS.len()
+    }
+}
@@ -27,28 +27,39 @@
 use crate::Demands;
 use crate::Mixer;
 use crate::Motors;
+use crate::mixers::MotorMix;
+use crate::mixers::saturation::SaturationConfig;
+use crate::mixers::saturation::mix_limited;
+
+pub const MOTORS: [MotorMix; 4] = [
+
+    // right rear
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: 1.0, yaw: 1.0 },
+
+    // right front
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: -1.0, yaw: -1.0 },
+
+    // left rear
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: 1.0, yaw: -1.0 },
+
+    // left front
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: -1.0, yaw: 1.0 }
+];
 
 pub struct QuadXbf {
 
+    pub saturation: SaturationConfig
 }
 
 impl Mixer for QuadXbf {
 
     fn get_motors(&self, demands: &Demands) -> Motors {
-        
-        Motors {
-
-            // right rear
-            m1: demands.throttle - demands.roll + demands.pitch + demands.yaw, 
 
-            // right front
-            m2: demands.throttle - demands.roll - demands.pitch - demands.yaw, 
+        mix_limited(&MOTORS, demands, &self.saturation)
+    }
 
-            // left rear
-            m3: demands.throttle + demands.roll + demands.pitch - demands.yaw, 
+    fn motor_count(&self) -> usize {
 
-            // left front
-            m4: demands.throttle + demands.roll - demands.pitch + demands.yaw  
-        }
+        MOTORS.len()
     }
 }
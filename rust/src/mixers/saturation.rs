@@ -0,0 +1,169 @@
+/*
+   Keeping mixed motor values in [0,1] without losing attitude control
+
+   When the mix doesn't fit, the throttle is shifted to make room for the
+   roll, pitch and yaw corrections; if they still don't fit, they are scaled
+   back, yaw first unless configured otherwise.  Without airmode the
+   throttle is only ever lowered, so at zero throttle the motors stop and
+   corrections are lost; with airmode it can also be raised, so that the
+   vehicle stays controllable at zero throttle (e.g. during flips).
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::Demands;
+use crate::MAX_MOTORS;
+use crate::Motors;
+use crate::mixers::MotorMix;
+use crate::mixers::mix;
+use crate::utils::constrain_f;
+
+// Which corrections are given up first when the motors saturate
+#[repr(C)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Priority {
+
+    // Yaw is scaled back before roll and pitch, as in PX4
+    RollPitch,
+
+    // Roll, pitch and yaw are scaled back together, as in Betaflight
+    Equal
+}
+
+#[repr(C)]
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SaturationConfig {
+
+    pub priority: Priority,
+
+    pub airmode: bool
+}
+
+pub const DEFAULT_CONFIG: SaturationConfig = SaturationConfig {
+    priority: Priority::RollPitch,
+    airmode: false
+};
+
+// Mixes the demands by the table, as mixers::mix() does, keeping the motors
+// in [0,1].  Every motor's throttle share must be positive; otherwise the
+// motors are just constrained.
+pub fn mix_limited(table: &[MotorMix], demands: &Demands, config: &SaturationConfig) -> Motors {
+
+    let count = table.len().min(MAX_MOTORS);
+
+    let table = &table[..count];
+
+    let throttle = constrain_f(demands.throttle, 0.0, 1.0);
+
+    let mut motors = mix(table, demands);
+
+    let fits = motors.as_slice().iter().all(|value| (0.0..=1.0).contains(value));
+
+    if !fits && table.iter().all(|row| row.throttle > 0.0) {
+
+        let part = |roll, pitch, yaw| {
+            mix(table, &Demands { throttle: 0.0, roll, pitch, yaw }).values
+        };
+
+        let cyclic = part(demands.roll, demands.pitch, 0.0);
+        let yaw = part(0.0, 0.0, demands.yaw);
+
+        let mut corrections = [0.0; MAX_MOTORS];
+
+        match config.priority {
+
+            Priority::RollPitch => {
+
+                let k_cyclic = fit(table, &corrections, &cyclic);
+
+                add(&mut corrections, &cyclic, k_cyclic);
+
+                let k_yaw = fit(table, &corrections, &yaw);
+
+                add(&mut corrections, &yaw, k_yaw);
+            }
+
+            Priority::Equal => {
+
+                let mut all = cyclic;
+
+                add(&mut all, &yaw, 1.0);
+
+                let k = fit(table, &corrections, &all);
+
+                add(&mut corrections, &all, k);
+            }
+        }
+
+        // Throttles that keep every motor in [0,1]
+        let (low, high) = table
+            .iter()
+            .zip(corrections)
+            .fold((f32::MIN, f32::MAX), |(low, high), (row, c)| {
+                ((-c / row.throttle).max(low), ((1.0 - c) / row.throttle).min(high))
+            });
+
+        let throttle = if config.airmode {
+            constrain_f(throttle, low, high)
+        } else {
+            throttle.min(high)
+        };
+
+        for (k, row) in table.iter().enumerate() {
+            motors.values[k] = row.throttle * throttle + corrections[k];
+        }
+    }
+
+    for value in motors.values.iter_mut() {
+        *value = constrain_f(*value, 0.0, 1.0);
+    }
+
+    motors
+}
+
+// Largest k in [0,1] for which base + k * extra fits in [0,1] at some
+// throttle, given that base does.  Every pair of motors bounds k: the
+// throttle that the first needs to stay above 0 mustn't push the second
+// above 1.
+fn fit(table: &[MotorMix], base: &[f32; MAX_MOTORS], extra: &[f32; MAX_MOTORS]) -> f32 {
+
+    let mut k: f32 = 1.0;
+
+    for (i, row_i) in table.iter().enumerate() {
+
+        for (j, row_j) in table.iter().enumerate() {
+
+            let c = extra[j] / row_j.throttle - extra[i] / row_i.throttle;
+            let d = (1.0 - base[j]) / row_j.throttle + base[i] / row_i.throttle;
+
+            if c > 0.0 {
+                k = k.min(d / c);
+            }
+        }
+    }
+
+    k.max(0.0)
+}
+
+fn add(values: &mut [f32; MAX_MOTORS], more: &[f32; MAX_MOTORS], scale: f32) {
+
+    for (value, more) in values.iter_mut().zip(more) {
+        *value += scale * more;
+    }
+}
@@ -0,0 +1,70 @@
+/*
+    Mixer values for tricopters using Betaflight motor layout.  The motors
+    don't yaw the vehicle; a servo tilting the rear motor does, following the
+    yaw demand.
+
+    3       2
+     \     /
+      \ ^ /
+        |
+        |
+        1 (tilted by the servo)
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::Demands;
+use crate::Mixer;
+use crate::Motors;
+use crate::mixers::MotorMix;
+use crate::mixers::saturation::SaturationConfig;
+use crate::mixers::saturation::mix_limited;
+use crate::utils::constrain_f;
+
+pub const MOTORS: [MotorMix; 3] = [
+
+    // rear
+    MotorMix { throttle: 1.0, roll: 0.0, pitch: 1.333333, yaw: 0.0 },
+
+    // right
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: -0.666667, yaw: 0.0 },
+
+    // left
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: -0.666667, yaw: 0.0 }
+];
+
+pub struct Tricopter {
+
+    pub saturation: SaturationConfig
+}
+
+impl Mixer for Tricopter {
+
+    fn get_motors(&self, demands: &Demands) -> Motors {
+
+        let mut motors = mix_limited(&MOTORS, demands, &self.saturation);
+
+        motors.servo = constrain_f(demands.yaw, -1.0, 1.0);
+
+        motors
+    }
+
+    fn motor_count(&self) -> usize {
+
+        MOTORS.len()
+    }
+}
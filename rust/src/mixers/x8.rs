@@ -0,0 +1,78 @@
+/*
+    Mixer values for X8 frames (coaxial quad-X) using Betaflight motor
+    layout, with motors 1-4 on top and 5-8 beneath them:
+
+    4cw/8ccw   2ccw/6cw
+           \ /
+            ^
+           / \
+   3ccw/7cw   1cw/5ccw
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::Demands;
+use crate::Mixer;
+use crate::Motors;
+use crate::mixers::MotorMix;
+use crate::mixers::saturation::SaturationConfig;
+use crate::mixers::saturation::mix_limited;
+
+pub const MOTORS: [MotorMix; 8] = [
+
+    // right rear
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: 1.0, yaw: 1.0 },
+
+    // right front
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: -1.0, yaw: -1.0 },
+
+    // left rear
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: 1.0, yaw: -1.0 },
+
+    // left front
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: -1.0, yaw: 1.0 },
+
+    // under right rear
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: 1.0, yaw: -1.0 },
+
+    // under right front
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: -1.0, yaw: 1.0 },
+
+    // under left rear
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: 1.0, yaw: 1.0 },
+
+    // under left front
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: -1.0, yaw: -1.0 }
+];
+
+pub struct X8 {
+
+    pub saturation: SaturationConfig
+}
+
+impl Mixer for X8 {
+
+    fn get_motors(&self, demands: &Demands) -> Motors {
+
+        mix_limited(&MOTORS, demands, &self.saturation)
+    }
+
+    fn motor_count(&self) -> usize {
+
+        MOTORS.len()
+    }
+}
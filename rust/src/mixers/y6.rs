@@ -0,0 +1,73 @@
+/*
+    Mixer values for Y6 frames (coaxial Y) using Betaflight motor layout,
+    with motors 1-3 on top and 4-6 beneath them:
+
+   3cw/6ccw     2cw/5ccw
+          \     /
+           \ ^ /
+             |
+             |
+         1ccw/4cw
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::Demands;
+use crate::Mixer;
+use crate::Motors;
+use crate::mixers::MotorMix;
+use crate::mixers::saturation::SaturationConfig;
+use crate::mixers::saturation::mix_limited;
+
+pub const MOTORS: [MotorMix; 6] = [
+
+    // rear
+    MotorMix { throttle: 1.0, roll: 0.0, pitch: 1.333333, yaw: -1.0 },
+
+    // right
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: -0.666667, yaw: 1.0 },
+
+    // left
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: -0.666667, yaw: 1.0 },
+
+    // under rear
+    MotorMix { throttle: 1.0, roll: 0.0, pitch: 1.333333, yaw: 1.0 },
+
+    // under right
+    MotorMix { throttle: 1.0, roll: -1.0, pitch: -0.666667, yaw: -1.0 },
+
+    // under left
+    MotorMix { throttle: 1.0, roll: 1.0, pitch: -0.666667, yaw: -1.0 }
+];
+
+pub struct Y6 {
+
+    pub saturation: SaturationConfig
+}
+
+impl Mixer for Y6 {
+
+    fn get_motors(&self, demands: &Demands) -> Motors {
+
+        mix_limited(&MOTORS, demands, &self.saturation)
+    }
+
+    fn motor_count(&self) -> usize {
+
+        MOTORS.len()
+    }
+}
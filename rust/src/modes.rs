@@ -0,0 +1,246 @@
+/*
+   Flight modes selected by aux-channel ranges, as in Betaflight's modes tab
+
+   Each range maps a span of an aux channel's [1000,2000] value to a mode;
+   a mode is on while any of its ranges contains its channel.  Modes combine
+   as in Betaflight: Angle takes priority over Horizon, with Acro when
+   neither is on, and AltHold can be added to any of them.  PosHold and
+   Failsafe imply Angle and AltHold; until there is a position controller,
+   PosHold levels and holds altitude without holding position.  Altitude
+   modes need a valid altitude.  Angle controllers can't be made without a
+   level gain (k_level_p), so that failsafe can always level the vehicle.
+
+   The active modes decide which controllers run.  A controller switched in
+   is primed from the demands the pipeline was producing, so that its output
+   does not jump.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::Demands;
+use crate::Mixer;
+use crate::Motors;
+use crate::VehicleState;
+use crate::failsafe::Failsafe;
+use crate::failsafe::Phase;
+use crate::make_motors_off;
+use crate::pids;
+use crate::pids::InvalidConfig;
+use crate::pids::LevelMode;
+use crate::pids::check;
+use crate::receiver;
+use crate::receiver::Receiver;
+
+pub const MAX_RANGES: usize = 8;
+
+pub const MAX_CONTROLLERS: usize = 8;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FlightMode {
+
+    Acro,
+    Angle,
+    Horizon,
+    AltHold,
+    PosHold,
+    Failsafe
+}
+
+// Active while aux channel `aux` (0=aux1) is in [start,end)
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModeRange {
+
+    pub mode: FlightMode,
+    pub aux: usize,
+    pub start: u16,
+    pub end: u16
+}
+
+#[derive(Clone)]
+pub struct Modes {
+
+    ranges: [Option<ModeRange>; MAX_RANGES],
+
+    // Bit per FlightMode
+    active: u8,
+
+    controller_active: [bool; MAX_CONTROLLERS],
+
+    // Last demands out of the controllers; None before the first step
+    output: Option<Demands>
+}
+
+impl Modes {
+
+    // Returns false if there is no room left or the aux channel doesn't
+    // exist
+    pub fn add_range(&mut self, range: ModeRange) -> bool {
+
+        if range.aux >= receiver::AUX_COUNT {
+            return false;
+        }
+
+        match self.ranges.iter_mut().find(|r| r.is_none()) {
+            Some(slot) => { *slot = Some(range); true }
+            None => false
+        }
+    }
+
+    // Selects the modes from the aux channels and failsafe phase, returning
+    // the highest-priority one
+    pub fn update(
+        &mut self,
+        receiver: &Receiver,
+        failsafe: &Failsafe,
+        altitude_valid: bool) -> FlightMode {
+
+        let mut active = 0;
+
+        for range in self.ranges.iter().flatten() {
+
+            let value = receiver.aux(range.aux);
+
+            if value >= range.start as f32 && value < range.end as f32 {
+                active |= bit(range.mode);
+            }
+        }
+
+        // Guard time leaves the pilot in control
+        if matches!(failsafe.phase(), Phase::Landing | Phase::Landed) {
+            active |= bit(FlightMode::Failsafe);
+        }
+
+        if active & (bit(FlightMode::PosHold) | bit(FlightMode::Failsafe)) != 0 {
+            active |= bit(FlightMode::Angle);
+
+            if altitude_valid {
+                active |= bit(FlightMode::AltHold);
+            }
+        }
+
+        if !altitude_valid {
+            active &= !(bit(FlightMode::AltHold) | bit(FlightMode::PosHold));
+        }
+
+        if active & bit(FlightMode::Angle) != 0 {
+            active &= !bit(FlightMode::Horizon);
+        }
+
+        if active & (bit(FlightMode::Angle) | bit(FlightMode::Horizon)) == 0 {
+            active |= bit(FlightMode::Acro);
+        }
+
+        self.active = active;
+
+        self.mode()
+    }
+
+    pub fn is_active(&self, mode: FlightMode) -> bool {
+
+        self.active & bit(mode) != 0
+    }
+
+    pub fn mode(&self) -> FlightMode {
+
+        [
+            FlightMode::Failsafe,
+            FlightMode::PosHold,
+            FlightMode::AltHold,
+            FlightMode::Angle,
+            FlightMode::Horizon
+        ]
+            .into_iter()
+            .find(|&mode| self.is_active(mode))
+            .unwrap_or(FlightMode::Acro)
+    }
+
+    pub fn level_mode(&self) -> LevelMode {
+
+        if self.is_active(FlightMode::Angle) {
+            LevelMode::Angle
+        } else if self.is_active(FlightMode::Horizon) {
+            LevelMode::Horizon
+        } else {
+            LevelMode::Acro
+        }
+    }
+
+    // Like crate::step(), running only the controllers the active modes
+    // call for; fails, running none, if there are more than MAX_CONTROLLERS
+    #[allow(clippy::too_many_arguments)]
+    pub fn step(
+        &mut self,
+        stick_demands: &Demands,
+        state: &VehicleState,
+        arr: &mut [pids::Controller],
+        pid_reset: &bool,
+        armed: &bool,
+        usec: &u32,
+        mixer: &dyn Mixer) -> Result<Motors, InvalidConfig> {
+
+        check(arr.len() <= MAX_CONTROLLERS, "controllers")?;
+
+        let mut demands = stick_demands.clone();
+
+        // Before the first step, controllers switched in take over from the
+        // sticks
+        let output = self.output.clone().unwrap_or_else(|| stick_demands.clone());
+
+        let level_mode = self.level_mode();
+
+        for (pid, was_active) in
+            arr.iter_mut().zip(self.controller_active.iter_mut()) {
+
+            let active = match *pid {
+                pids::Controller::AltHold { .. } => self.active & bit(FlightMode::AltHold) != 0,
+                _ => true
+            };
+
+            if active && !*was_active {
+                pids::activate(pid, &demands, state, &output);
+            }
+
+            *was_active = active;
+
+            if active {
+                pids::set_level_mode(pid, level_mode);
+                demands = pids::update(pid, *usec, demands, *state, *pid_reset || !*armed);
+            }
+        }
+
+        self.output = Some(demands.clone());
+
+        Ok(if *armed { mixer.get_motors(&demands) } else { make_motors_off(mixer.motor_count()) })
+    }
+}
+
+pub fn make() -> Modes {
+
+    Modes {
+        ranges: [None; MAX_RANGES],
+        active: bit(FlightMode::Acro),
+        controller_active: [false; MAX_CONTROLLERS],
+        output: None
+    }
+}
+
+fn bit(mode: FlightMode) -> u8 {
+
+    1 << (mode as u8)
+}
@@ -0,0 +1,508 @@
+/*
+   MultiWii Serial Protocol (MSP) parser and serializer, after the C++ Msp
+   class
+
+   Both MSPv1 ($M, XOR checksum) and MSPv2 ($X, CRC8 DVB-S2) frames are
+   supported.  Payloads are kept in fixed-size buffers, so this works without
+   an allocator.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+pub mod messages;
+
+// Largest payload we accept or send, as in the C++ firmware
+pub const BUF_SIZE: usize = 128;
+
+// Header and checksum bytes around the payload of an MSPv2 frame
+const V2_OVERHEAD: usize = 9;
+
+// A message with a fixed payload layout; implemented for each message in
+// messages.json
+pub trait Payload: Sized {
+
+    const ID: u16;
+
+    // Payload size in bytes
+    const SIZE: usize;
+
+    // Writes the fields, little-endian, into the first SIZE bytes of buf
+    fn encode(&self, buf: &mut [u8]);
+
+    // Reads the fields from a payload of at least SIZE bytes
+    fn decode(payload: &[u8]) -> Option<Self>;
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Version {
+
+    V1,
+    V2
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Direction {
+
+    // '<': to the flight controller
+    Request,
+
+    // '>': from the flight controller
+    Response,
+
+    // '!': from the flight controller, when a request was not understood
+    Error
+}
+
+impl Direction {
+
+    fn from_byte(c: u8) -> Option<Direction> {
+
+        match c {
+            b'<' => Some(Direction::Request),
+            b'>' => Some(Direction::Response),
+            b'!' => Some(Direction::Error),
+            _ => None
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+
+        match self {
+            Direction::Request => b'<',
+            Direction::Response => b'>',
+            Direction::Error => b'!'
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Message {
+
+    pub version: Version,
+    pub direction: Direction,
+    pub function: u16,
+    payload: [u8; BUF_SIZE],
+    size: usize
+}
+
+impl Message {
+
+    pub fn payload(&self) -> &[u8] {
+
+        &self.payload[..self.size]
+    }
+
+    // Little-endian short at the given index (not byte offset) of the
+    // payload, or zero past the end
+    pub fn parse_short(&self, index: usize) -> i16 {
+
+        let k = 2 * index;
+
+        if k + 2 > self.size {
+            return 0;
+        }
+
+        i16::from_le_bytes([self.payload[k], self.payload[k + 1]])
+    }
+
+    // The typed payload, if this is a message of that type
+    pub fn decode<P: Payload>(&self) -> Option<P> {
+
+        if self.function != P::ID {
+            return None;
+        }
+
+        P::decode(self.payload())
+    }
+}
+
+#[derive(Clone,Copy,PartialEq,Eq)]
+enum State {
+
+    Idle,
+    GotStart,
+    GotM,
+    GotX,
+    V1GotDirection,
+    V1GotSize,
+    V1InPayload,
+    V2GotDirection,
+    V2GotFlags,
+    V2GotFunctionLow,
+    V2GotFunctionHigh,
+    V2GotSizeLow,
+    V2InPayload,
+    GotPayload
+}
+
+#[derive(Clone)]
+pub struct Parser {
+
+    state: State,
+    checksum: u8,
+    index: usize,
+    message: Message
+}
+
+impl Parser {
+
+    // Consumes one byte, returning the message once a complete frame with a
+    // valid checksum has been received
+    pub fn parse(&mut self, c: u8) -> Option<&Message> {
+
+        let msg = &mut self.message;
+
+        self.state = match self.state {
+
+            State::Idle => {
+                if c == b'$' { State::GotStart } else { State::Idle }
+            }
+
+            State::GotStart => {
+                match c {
+                    b'M' => State::GotM,
+                    b'X' => State::GotX,
+                    _ => State::Idle
+                }
+            }
+
+            State::GotM | State::GotX => {
+
+                match Direction::from_byte(c) {
+
+                    Some(direction) => {
+
+                        msg.direction = direction;
+
+                        if self.state == State::GotM {
+                            msg.version = Version::V1;
+                            State::V1GotDirection
+                        } else {
+                            msg.version = Version::V2;
+                            State::V2GotDirection
+                        }
+                    }
+
+                    None => State::Idle
+                }
+            }
+
+            State::V1GotDirection => {
+
+                msg.size = c as usize;
+                self.checksum = c;
+
+                if msg.size > BUF_SIZE { State::Idle } else { State::V1GotSize }
+            }
+
+            State::V1GotSize => {
+
+                msg.function = c as u16;
+                self.checksum ^= c;
+                self.index = 0;
+
+                if msg.size > 0 { State::V1InPayload } else { State::GotPayload }
+            }
+
+            State::V1InPayload => {
+
+                self.checksum ^= c;
+
+                self.accumulate(c)
+            }
+
+            State::V2GotDirection => {
+
+                // Flags are unused, but covered by the CRC
+                self.checksum = crc8_dvb_s2(0, c);
+
+                State::V2GotFlags
+            }
+
+            State::V2GotFlags => {
+
+                msg.function = c as u16;
+                self.checksum = crc8_dvb_s2(self.checksum, c);
+
+                State::V2GotFunctionLow
+            }
+
+            State::V2GotFunctionLow => {
+
+                msg.function |= (c as u16) << 8;
+                self.checksum = crc8_dvb_s2(self.checksum, c);
+
+                State::V2GotFunctionHigh
+            }
+
+            State::V2GotFunctionHigh => {
+
+                msg.size = c as usize;
+                self.checksum = crc8_dvb_s2(self.checksum, c);
+
+                State::V2GotSizeLow
+            }
+
+            State::V2GotSizeLow => {
+
+                msg.size |= (c as usize) << 8;
+                self.checksum = crc8_dvb_s2(self.checksum, c);
+                self.index = 0;
+
+                if msg.size > BUF_SIZE {
+                    State::Idle
+                } else if msg.size > 0 {
+                    State::V2InPayload
+                } else {
+                    State::GotPayload
+                }
+            }
+
+            State::V2InPayload => {
+
+                self.checksum = crc8_dvb_s2(self.checksum, c);
+
+                self.accumulate(c)
+            }
+
+            State::GotPayload => {
+
+                self.state = State::Idle;
+
+                return if c == self.checksum { Some(&self.message) } else { None };
+            }
+        };
+
+        None
+    }
+
+    pub fn is_idle(&self) -> bool {
+
+        self.state == State::Idle
+    }
+
+    fn accumulate(&mut self, c: u8) -> State {
+
+        self.message.payload[self.index] = c;
+        self.index += 1;
+
+        if self.index < self.message.size { self.state } else { State::GotPayload }
+    }
+}
+
+pub fn make_parser() -> Parser {
+
+    Parser {
+        state: State::Idle,
+        checksum: 0,
+        index: 0,
+        message: Message {
+            version: Version::V1,
+            direction: Direction::Request,
+            function: 0,
+            payload: [0; BUF_SIZE],
+            size: 0
+        }
+    }
+}
+
+// Builds one outgoing frame at a time, which can then be sent whole or read
+// out byte by byte
+#[derive(Clone)]
+pub struct Serializer {
+
+    buf: [u8; BUF_SIZE + V2_OVERHEAD],
+    size: usize,
+    index: usize,
+    checksum: u8
+}
+
+impl Serializer {
+
+    // Returns false, leaving nothing to send, if the payload is too big or
+    // the function does not fit in an MSPv1 frame
+    pub fn serialize_bytes(
+        &mut self,
+        version: Version,
+        direction: Direction,
+        function: u16,
+        src: &[u8]) -> bool {
+
+        self.prepare(version, direction, function, src.len()) && {
+
+            for &b in src {
+                self.add(version, b);
+            }
+
+            self.put(self.checksum);
+
+            true
+        }
+    }
+
+    // Shorts are sent little-endian
+    pub fn serialize_shorts(
+        &mut self,
+        version: Version,
+        direction: Direction,
+        function: u16,
+        src: &[i16]) -> bool {
+
+        self.prepare(version, direction, function, 2 * src.len()) && {
+
+            for s in src {
+                for b in s.to_le_bytes() {
+                    self.add(version, b);
+                }
+            }
+
+            self.put(self.checksum);
+
+            true
+        }
+    }
+
+    pub fn serialize<P: Payload>(
+        &mut self,
+        version: Version,
+        direction: Direction,
+        message: &P) -> bool {
+
+        let mut buf = [0; BUF_SIZE];
+
+        if P::SIZE > BUF_SIZE {
+            self.size = 0;
+            self.index = 0;
+            return false;
+        }
+
+        message.encode(&mut buf[..P::SIZE]);
+
+        self.serialize_bytes(version, direction, P::ID, &buf[..P::SIZE])
+    }
+
+    // The frame built by the last call to one of the serialize methods
+    pub fn bytes(&self) -> &[u8] {
+
+        &self.buf[..self.size]
+    }
+
+    pub fn available(&self) -> usize {
+
+        self.size - self.index
+    }
+
+    pub fn read(&mut self) -> u8 {
+
+        let b = self.buf[self.index];
+
+        self.index += 1;
+
+        b
+    }
+
+    fn prepare(
+        &mut self,
+        version: Version,
+        direction: Direction,
+        function: u16,
+        size: usize) -> bool {
+
+        self.size = 0;
+        self.index = 0;
+        self.checksum = 0;
+
+        let fits = match version {
+            Version::V1 => function <= u8::MAX as u16,
+            Version::V2 => true
+        };
+
+        if !fits || size > BUF_SIZE {
+            return false;
+        }
+
+        let [function_lo, function_hi] = function.to_le_bytes();
+        let [size_lo, size_hi] = (size as u16).to_le_bytes();
+
+        self.put(b'$');
+
+        match version {
+
+            Version::V1 => {
+
+                self.put(b'M');
+                self.put(direction.to_byte());
+
+                self.add(version, size_lo);
+                self.add(version, function_lo);
+            }
+
+            Version::V2 => {
+
+                self.put(b'X');
+                self.put(direction.to_byte());
+
+                // Flags
+                self.add(version, 0);
+                self.add(version, function_lo);
+                self.add(version, function_hi);
+                self.add(version, size_lo);
+                self.add(version, size_hi);
+            }
+        }
+
+        true
+    }
+
+    fn put(&mut self, b: u8) {
+
+        self.buf[self.size] = b;
+        self.size += 1;
+    }
+
+    // Adds a byte covered by the checksum
+    fn add(&mut self, version: Version, b: u8) {
+
+        self.checksum = match version {
+            Version::V1 => self.checksum ^ b,
+            Version::V2 => crc8_dvb_s2(self.checksum, b)
+        };
+
+        self.put(b);
+    }
+}
+
+pub fn make_serializer() -> Serializer {
+
+    Serializer {
+        buf: [0; BUF_SIZE + V2_OVERHEAD],
+        size: 0,
+        index: 0,
+        checksum: 0
+    }
+}
+
+pub fn crc8_dvb_s2(crc: u8, a: u8) -> u8 {
+
+    let mut crc = crc ^ a;
+
+    for _ in 0..8 {
+        crc = if crc & 0x80 != 0 { (crc << 1) ^ 0xD5 } else { crc << 1 };
+    }
+
+    crc
+}
@@ -0,0 +1,241 @@
+/*
+   Motor output conditioning, after Betaflight's mixer and motor code
+
+   Takes the mixer's motor values in [0,1] to ESC commands, so that the same
+   demands give the same thrust across props and packs:
+
+   1. Thrust linearization, undoing the roughly quadratic thrust curve of a
+      prop, so that thrust follows the motor value
+   2. Battery-sag compensation, scaling the outputs up as the pack voltage
+      drops below the one the vehicle was tuned at
+   3. Idle, keeping armed motors spinning at zero throttle
+   4. Mapping to the ESC protocol's range
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::MAX_MOTORS;
+use crate::Motors;
+use crate::math;
+use crate::pids::InvalidConfig;
+use crate::pids::check;
+use crate::utils::constrain_f;
+
+pub const DSHOT_STOP: u16 = 0;
+pub const DSHOT_MIN: u16 = 48;
+pub const DSHOT_MAX: u16 = 2047;
+
+// Servo pulse widths in microseconds
+pub const SERVO_CENTER_USEC: u16 = 1500;
+pub const SERVO_RANGE_USEC: u16 = 500;
+
+// Largest boost from sag compensation, so that a bad voltage reading can't
+// send the motors to full; a pack above the reference voltage gets none
+const MAX_SAG_FACTOR: f32 = 1.5;
+
+#[repr(C)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Protocol {
+
+    // Pulse widths in microseconds: stopped (Betaflight's min_command), and
+    // the range that motor values [0,1] map to
+    Pwm { stop_usec: u16, min_usec: u16, max_usec: u16 },
+
+    // Throttle values DSHOT_MIN..DSHOT_MAX, with DSHOT_STOP for stopped
+    Dshot
+}
+
+#[repr(C)]
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OutputConfig {
+
+    // Fraction of the output range that armed motors idle at, in [0,0.5)
+    pub idle: f32,
+
+    // How quadratic the props' thrust curve is, in [0,1]; zero turns
+    // linearization off
+    pub thrust_linear: f32,
+
+    // How much of the sag to make up, in [0,1]; zero turns compensation off
+    pub sag_compensation: f32,
+
+    // Pack voltage the vehicle was tuned at
+    pub reference_voltage: f32,
+
+    pub protocol: Protocol
+}
+
+// Betaflight's defaults, on a full 4S pack
+pub const DEFAULT_CONFIG: OutputConfig = OutputConfig {
+    idle: 0.055,
+    thrust_linear: 0.0,
+    sag_compensation: 0.0,
+    reference_voltage: 16.8,
+    protocol: Protocol::Dshot
+};
+
+impl OutputConfig {
+
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+
+        check((0.0..0.5).contains(&self.idle), "idle")?;
+        check((0.0..=1.0).contains(&self.thrust_linear), "thrust_linear")?;
+        check((0.0..=1.0).contains(&self.sag_compensation), "sag_compensation")?;
+        check(self.reference_voltage > 0.0, "reference_voltage")?;
+
+        if let Protocol::Pwm { stop_usec, min_usec, max_usec } = self.protocol {
+            check(stop_usec <= min_usec && min_usec < max_usec, "protocol")?;
+        }
+
+        Ok(())
+    }
+}
+
+// Only the first `count` values are used, in the mixer's motor order
+#[repr(C)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Commands {
+
+    pub count: usize,
+    pub values: [u16; MAX_MOTORS],
+
+    // Yaw servo pulse width in microseconds
+    pub servo_usec: u16
+}
+
+impl Commands {
+
+    pub fn as_slice(&self) -> &[u16] {
+
+        &self.values[..self.count]
+    }
+}
+
+#[derive(Clone)]
+pub struct Output {
+
+    config: OutputConfig
+}
+
+impl Output {
+
+    // Motor values after linearization, sag compensation and idle, still in
+    // [0,1] (e.g. for a simulator); the voltage is None when not measured
+    pub fn condition(&self, motors: &Motors, voltage: Option<f32>) -> Motors {
+
+        let sag_factor = self.sag_factor(voltage);
+
+        let idle = self.config.idle;
+
+        let mut conditioned = *motors;
+
+        for value in conditioned.values.iter_mut().take(motors.count) {
+
+            let linear = self.linearize(constrain_f(*value, 0.0, 1.0));
+
+            let compensated = constrain_f(linear * sag_factor, 0.0, 1.0);
+
+            *value = idle + (1.0 - idle) * compensated;
+        }
+
+        conditioned
+    }
+
+    // ESC commands: stopped when disarmed
+    pub fn get_commands(&self, motors: &Motors, armed: bool, voltage: Option<f32>) -> Commands {
+
+        let mut commands = Commands {
+            count: motors.count.min(MAX_MOTORS),
+            values: [0; MAX_MOTORS],
+            servo_usec: SERVO_CENTER_USEC
+        };
+
+        let (stop, min, max) = match self.config.protocol {
+            Protocol::Pwm { stop_usec, min_usec, max_usec } => (stop_usec, min_usec, max_usec),
+            Protocol::Dshot => (DSHOT_STOP, DSHOT_MIN, DSHOT_MAX)
+        };
+
+        if !armed {
+            commands.values = [stop; MAX_MOTORS];
+            return commands;
+        }
+
+        let conditioned = self.condition(motors, voltage);
+
+        for (command, value) in commands.values.iter_mut().zip(conditioned.as_slice()) {
+            *command = min + round(value * (max - min) as f32);
+        }
+
+        commands.servo_usec = SERVO_CENTER_USEC.saturating_add_signed(
+            round_signed(constrain_f(motors.servo, -1.0, 1.0) * SERVO_RANGE_USEC as f32));
+
+        commands
+    }
+
+    // Inverse of thrust = t * value^2 + (1 - t) * value
+    fn linearize(&self, value: f32) -> f32 {
+
+        let t = self.config.thrust_linear;
+
+        if t == 0.0 || value == 0.0 {
+            return value;
+        }
+
+        let b = (1.0 - t) / (2.0 * t);
+
+        math::sqrt(value / t + b * b) - b
+    }
+
+    fn sag_factor(&self, voltage: Option<f32>) -> f32 {
+
+        match voltage {
+
+            Some(volts) if volts > 0.0 => {
+
+                let full = self.config.reference_voltage / volts;
+
+                let factor = 1.0 + self.config.sag_compensation * (full - 1.0);
+
+                constrain_f(factor, 1.0, MAX_SAG_FACTOR)
+            }
+
+            _ => 1.0
+        }
+    }
+}
+
+// As make(DEFAULT_CONFIG), which can't fail, e.g. for a static
+pub const DEFAULT_OUTPUT: Output = Output { config: DEFAULT_CONFIG };
+
+pub fn make(config: OutputConfig) -> Result<Output, InvalidConfig> {
+
+    config.validate()?;
+
+    Ok(Output { config })
+}
+
+fn round(x: f32) -> u16 {
+
+    (x + 0.5) as u16
+}
+
+fn round_signed(x: f32) -> i16 {
+
+    if x < 0.0 { -((0.5 - x) as i16) } else { (x + 0.5) as i16 }
+}
@@ -20,10 +20,29 @@
 
 use crate::Demands;
 use crate::VehicleState;
+use crate::clock::LoopConfig;
+use crate::rates::RateProfile;
 
 mod angle;
 mod althold;
 
+pub use angle::AnglePidConfig;
+pub use angle::LevelMode;
+pub use althold::AltHoldConfig;
+
+pub const DEFAULT_ANGLE_CONFIG: AnglePidConfig = angle::DEFAULT_CONFIG;
+
+pub const DEFAULT_ALT_HOLD_CONFIG: AltHoldConfig = althold::DEFAULT_CONFIG;
+
+// Names the first configuration field found out of range
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct InvalidConfig {
+
+    pub field: &'static str
+}
+
+// Controllers are kept inline rather than boxed, for embedded targets
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone)]
 pub enum Controller {
 
@@ -32,24 +51,80 @@ pub enum Controller {
     AltHold { altpid: althold::Pid },
 }
 
+// Failsafe levels the vehicle in Angle mode, whatever the modes, so the
+// level gain can't be zero
 pub fn make_angle(
     k_rate_p: f32,
     k_rate_i: f32,
     k_rate_d: f32,
     k_rate_f: f32,
-    k_level_p: f32 ) -> Controller {
+    k_level_p: f32,
+    loop_config: &LoopConfig,
+    config: &AnglePidConfig) -> Result<Controller, InvalidConfig> {
 
-    Controller::Angle {
-        angpid: angle::make(k_rate_p, k_rate_i, k_rate_d, k_rate_f, k_level_p)
-    }
+    check(k_level_p > 0.0, "k_level_p")?;
+
+    Ok(Controller::Angle {
+        angpid: angle::make(
+                    k_rate_p, k_rate_i, k_rate_d, k_rate_f, k_level_p, loop_config, config)?
+    })
 }
 
 pub fn make_alt_hold(
     k_p: f32,
-    k_i: f32) -> Controller {
+    k_i: f32,
+    config: &AltHoldConfig) -> Result<Controller, InvalidConfig> {
 
-    Controller::AltHold {
-        altpid: althold::make(k_p, k_i) 
+    Ok(Controller::AltHold {
+        altpid: althold::make(k_p, k_i, config)?
+    })
+}
+
+// Throttle stick demand that makes an altitude-hold controller climb (or
+// descend) at the given rate in meters per second
+pub fn alt_hold_throttle(config: &AltHoldConfig, climb_rate: f32) -> f32 {
+
+    althold::throttle_for_climb_rate(config, climb_rate)
+}
+
+// Sets how an angle controller interprets the cyclic sticks; other
+// controllers are unaffected
+pub fn set_level_mode(t: &mut Controller, level_mode: LevelMode) {
+
+    if let Controller::Angle { ref mut angpid } = *t {
+        angle::set_level_mode(angpid, level_mode);
+    }
+}
+
+// Sets the stick rate curves of an angle controller; other controllers are
+// unaffected
+pub fn set_rates(t: &mut Controller, rates: &RateProfile) -> Result<(), InvalidConfig> {
+
+    rates.validate()?;
+
+    if let Controller::Angle { ref mut angpid } = *t {
+        angle::set_rates(angpid, rates);
+    }
+
+    Ok(())
+}
+
+// Called when a controller is switched into the pipeline, with the demands
+// the pipeline was producing, for bumpless transfer
+pub fn activate(
+    t: &mut Controller,
+    demands: &Demands,
+    vstate: &VehicleState,
+    output: &Demands) {
+
+    match *t {
+
+        // Rate integrators carry over between level modes
+        Controller::Angle { .. } => { },
+
+        Controller::AltHold {ref mut altpid} => {
+            althold::activate(altpid, demands, vstate, output.throttle)
+        }
     }
 }
 
@@ -71,3 +146,8 @@ pub fn update(
         }
     }
 }
+
+pub(crate) fn check(ok: bool, field: &'static str) -> Result<(), InvalidConfig> {
+
+    if ok { Ok(()) } else { Err(InvalidConfig { field }) }
+}
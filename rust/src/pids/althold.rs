@@ -22,13 +22,50 @@ use crate::Demands;
 use crate::VehicleState;
 use crate::utils;
 
-const ALTITUDE_MIN: f32   = 1.0;
-const PILOT_VELZ_MAX: f32 = 2.5;
-const STICK_DEADBAND: f32 = 0.2;
-const WINDUP_MAX: f32     = 0.4;
+use super::InvalidConfig;
+use super::check;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AltHoldConfig {
+
+    // Meters; altitude is only held above this
+    pub altitude_min: f32,
+
+    // Climb rate at full stick, in meters per second
+    pub pilot_velz_max: f32,
+
+    // Fraction of the throttle stick's half-travel around center that means
+    // "hold"
+    pub stick_deadband: f32,
+
+    pub windup_max: f32
+}
+
+pub const DEFAULT_CONFIG: AltHoldConfig = AltHoldConfig {
+    altitude_min: 1.0,
+    pilot_velz_max: 2.5,
+    stick_deadband: 0.2,
+    windup_max: 0.4
+};
+
+impl AltHoldConfig {
+
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+
+        check(self.altitude_min >= 0.0, "altitude_min")?;
+        check(self.pilot_velz_max > 0.0, "pilot_velz_max")?;
+
+        // Climb-rate demands need room outside the deadband
+        check((0.0..0.9).contains(&self.stick_deadband), "stick_deadband")?;
+
+        check(self.windup_max > 0.0, "windup_max")
+    }
+}
 
 #[derive(Debug,Clone)]
 pub struct Pid { 
+    config: AltHoldConfig,
     k_p : f32,
     k_i: f32, 
     in_band_prev: bool,
@@ -38,17 +75,73 @@ pub struct Pid {
 
 pub fn make(
     k_p: f32,
-    k_i: f32) -> Pid {
+    k_i: f32,
+    config: &AltHoldConfig) -> Result<Pid, InvalidConfig> {
+
+    config.validate()?;
 
-    Pid {
-        k_p: k_p,
-        k_i: k_i,
+    Ok(Pid {
+        config: *config,
+        k_p,
+        k_i,
         in_band_prev: false,
         error_integral: 0.0,
         altitude_target: 0.0
-    }
+    })
 } 
 
+// Throttle stick demand in [0,1] commanding the given climb rate in
+// meters per second, outside the deadband
+pub fn throttle_for_climb_rate(config: &AltHoldConfig, velz: f32) -> f32 {
+
+    let sthrottle = utils::constrain_abs(velz / config.pilot_velz_max, 1.0);
+
+    // Push small rates safely out of the deadband, where they would mean
+    // "hold"
+    let min = 1.1 * config.stick_deadband;
+
+    let sthrottle = if sthrottle.abs() < min {
+        if velz < 0.0 { -min } else { min }
+    } else {
+        sthrottle
+    };
+
+    (sthrottle + 1.0) / 2.0
+}
+
+// Primes the controller on engaging, so that its first output throttle
+// matches the throttle the vehicle was flying with, as far as the windup
+// limit allows
+pub fn activate(
+    pid: &mut Pid,
+    demands: &Demands,
+    vstate: &VehicleState,
+    throttle: f32) {
+
+    let altitude = vstate.z;
+
+    // [0,1] => [-1,+1]
+    let sthrottle = 2.0 * demands.throttle - 1.0; 
+
+    let in_band = sthrottle.abs() < pid.config.stick_deadband && altitude > pid.config.altitude_min; 
+
+    pid.in_band_prev = in_band;
+    pid.altitude_target = altitude;
+
+    let target_velocity = if in_band { 0.0 } else { pid.config.pilot_velz_max * sthrottle };
+
+    let error = target_velocity - vstate.dz;
+
+    // get_demands() adds this cycle's error before applying the I term
+    let integral = if pid.k_i > 0.0 {
+        (throttle - demands.throttle - error * pid.k_p) / pid.k_i - error
+    } else {
+        0.0
+    };
+
+    pid.error_integral = utils::constrain_abs(integral, pid.config.windup_max);
+}
+
 pub fn get_demands(
     pid: &mut Pid,
     demands: &Demands,
@@ -62,7 +155,7 @@ pub fn get_demands(
     let sthrottle = 2.0 * demands.throttle - 1.0; 
 
     // Is stick demand in deadband, above a minimum altitude?
-    let in_band = sthrottle.abs() < STICK_DEADBAND && altitude > ALTITUDE_MIN; 
+    let in_band = sthrottle.abs() < pid.config.stick_deadband && altitude > pid.config.altitude_min; 
 
     // Reset controller when moving into deadband above a minimum altitude
     let got_new_target = in_band && !pid.in_band_prev;
@@ -76,13 +169,13 @@ pub fn get_demands(
 
     // Target velocity is a setpoint inside deadband, scaled constant outside
     let target_velocity =
-        if in_band {pid.altitude_target - altitude } else { PILOT_VELZ_MAX * sthrottle};
+        if in_band {pid.altitude_target - altitude } else { pid.config.pilot_velz_max * sthrottle};
 
     // Compute error as scaled target minus actual
     let error = target_velocity - dz;
 
     // Compute I term, avoiding windup
-    pid.error_integral = utils::constrain_abs(pid.error_integral + error, WINDUP_MAX);
+    pid.error_integral = utils::constrain_abs(pid.error_integral + error, pid.config.windup_max);
 
     // Adjust throttle demand based on error
     Demands { 
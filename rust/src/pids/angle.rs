@@ -25,56 +25,173 @@ use crate::filters;
 
 use crate::utils::constrain_f;
 
-use crate::clock::DT;
+use crate::clock::LoopConfig;
+use crate::rates::RateProfile;
+use crate::rates::Rates;
+use crate::rates;
 
-const DTERM_LPF1_DYN_MIN_HZ: f32 = 75.0;
-const DTERM_LPF1_DYN_MAX_HZ: f32 = 150.0;
-const DTERM_LPF2_HZ: f32 = 150.0;
-const D_MIN_LOWPASS_HZ: f32 = 35.0;  
-const ITERM_RELAX_CUTOFF: f32 = 15.0;
-const D_MIN_RANGE_HZ: f32 = 85.0;  
+use super::InvalidConfig;
+use super::check;
 
 // minimum of 5ms between updates
 const DYN_LPF_THROTTLE_UPDATE_DELAY_US: u32 = 5000; 
 
 const DYN_LPF_THROTTLE_STEPS: f32 = 100.0;
 
-const ITERM_LIMIT: f32 = 400.0;
+// PT2 lowpass cutoff to smooth the boost effect
+const D_MIN_GAIN_FACTOR: f32  = 0.00008;
+const D_MIN_SETPOINT_GAIN_FACTOR: f32 = 0.00008;
 
-const ITERM_WINDUP_POINT_PERCENT: f32 = 85.0;        
+const OUTPUT_SCALING: f32 = 1000.0;
 
-// Full iterm suppression in setpoint mode at high-passed setpoint rate > 40deg/sec
-const ITERM_RELAX_SETPOINT_THRESHOLD: f32 = 40.0;
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnglePidConfig {
 
-const D_MIN: f32 = 30.0;
-const D_MIN_GAIN: f32 = 37.0;
-const D_MIN_ADVANCE: f32 = 20.0;
+    // D-term lowpass whose cutoff rises with throttle
+    pub dterm_lpf1_dyn_min_hz: f32,
+    pub dterm_lpf1_dyn_max_hz: f32,
+    pub dyn_lpf_curve_expo: f32,
 
-const FEEDFORWARD_MAX_RATE_LIMIT: f32 = 900.0;
+    pub dterm_lpf2_hz: f32,
 
-const DYN_LPF_CURVE_EXPO: f32 = 5.0;
+    // D-term notch; zero center frequency disables it
+    pub dterm_notch_hz: f32,
+    pub dterm_notch_cutoff_hz: f32,
 
-// PT2 lowpass cutoff to smooth the boost effect
-const D_MIN_GAIN_FACTOR: f32  = 0.00008;
-const D_MIN_SETPOINT_GAIN_FACTOR: f32 = 0.00008;
+    pub d_min: f32,
+    pub d_min_gain: f32,
+    pub d_min_advance: f32,
+    pub d_min_lowpass_hz: f32,
+    pub d_min_range_hz: f32,
 
-const RATE_ACCEL_LIMIT: f32 = 0.0;
-const YAW_RATE_ACCEL_LIMIT: f32 = 0.0;
+    pub iterm_limit: f32,
+    pub iterm_windup_point_percent: f32,
+    pub iterm_relax_cutoff_hz: f32,
 
-const OUTPUT_SCALING: f32 = 1000.0;
-const  LIMIT_CYCLIC: f32 = 500.0; 
-const  LIMIT_YAW: f32 = 400.0;
+    // Full iterm suppression at high-passed setpoint rates above this, in
+    // degrees per second
+    pub iterm_relax_setpoint_threshold: f32,
+
+    pub feedforward_max_rate_limit: f32,
+
+    // Zero disables
+    pub rate_accel_limit: f32,
+    pub yaw_rate_accel_limit: f32,
+
+    pub limit_cyclic: f32,
+    pub limit_yaw: f32,
+
+    pub yaw_lowpass_hz: f32,
+
+    // Degrees
+    pub level_angle_limit: f32
+}
+
+// Betaflight defaults
+pub const DEFAULT_CONFIG: AnglePidConfig = AnglePidConfig {
+    dterm_lpf1_dyn_min_hz: 75.0,
+    dterm_lpf1_dyn_max_hz: 150.0,
+    dyn_lpf_curve_expo: 5.0,
+    dterm_lpf2_hz: 150.0,
+    dterm_notch_hz: 0.0,
+    dterm_notch_cutoff_hz: 0.0,
+    d_min: 30.0,
+    d_min_gain: 37.0,
+    d_min_advance: 20.0,
+    d_min_lowpass_hz: 35.0,
+    d_min_range_hz: 85.0,
+    iterm_limit: 400.0,
+    iterm_windup_point_percent: 85.0,
+    iterm_relax_cutoff_hz: 15.0,
+    iterm_relax_setpoint_threshold: 40.0,
+    feedforward_max_rate_limit: 900.0,
+    rate_accel_limit: 0.0,
+    yaw_rate_accel_limit: 0.0,
+    limit_cyclic: 500.0,
+    limit_yaw: 400.0,
+    yaw_lowpass_hz: 100.0,
+    level_angle_limit: 45.0
+};
+
+impl AnglePidConfig {
+
+    // Filter cutoffs must be below the Nyquist frequency of the loop
+    pub fn validate(&self, loop_config: &LoopConfig) -> Result<(), InvalidConfig> {
+
+        let nyquist = loop_config.rate_hz as f32 / 2.0;
+
+        let cutoff = |hz: f32| hz > 0.0 && hz < nyquist;
+
+        loop_config.validate()?;
+
+        check(cutoff(self.dterm_lpf1_dyn_min_hz), "dterm_lpf1_dyn_min_hz")?;
+        check(cutoff(self.dterm_lpf1_dyn_max_hz)
+            && self.dterm_lpf1_dyn_max_hz >= self.dterm_lpf1_dyn_min_hz,
+            "dterm_lpf1_dyn_max_hz")?;
+        check((0.0..=10.0).contains(&self.dyn_lpf_curve_expo), "dyn_lpf_curve_expo")?;
+        check(cutoff(self.dterm_lpf2_hz), "dterm_lpf2_hz")?;
+
+        if self.dterm_notch_hz != 0.0 {
+            check(cutoff(self.dterm_notch_hz), "dterm_notch_hz")?;
+            check(self.dterm_notch_cutoff_hz > 0.0
+                && self.dterm_notch_cutoff_hz < self.dterm_notch_hz,
+                "dterm_notch_cutoff_hz")?;
+        }
+
+        check(self.d_min >= 0.0, "d_min")?;
+        check(self.d_min_gain >= 0.0, "d_min_gain")?;
+        check(self.d_min_advance >= 0.0, "d_min_advance")?;
+        check(cutoff(self.d_min_lowpass_hz), "d_min_lowpass_hz")?;
+        check(cutoff(self.d_min_range_hz), "d_min_range_hz")?;
+
+        check(self.iterm_limit > 0.0, "iterm_limit")?;
+        check((0.0..100.0).contains(&self.iterm_windup_point_percent),
+            "iterm_windup_point_percent")?;
+        check(cutoff(self.iterm_relax_cutoff_hz), "iterm_relax_cutoff_hz")?;
+        check(self.iterm_relax_setpoint_threshold > 0.0, "iterm_relax_setpoint_threshold")?;
+
+        check(self.feedforward_max_rate_limit >= 0.0, "feedforward_max_rate_limit")?;
+
+        check(self.rate_accel_limit >= 0.0, "rate_accel_limit")?;
+        check(self.yaw_rate_accel_limit >= 0.0, "yaw_rate_accel_limit")?;
+
+        check(self.limit_cyclic > 0.0, "limit_cyclic")?;
+        check(self.limit_yaw > 0.0, "limit_yaw")?;
+
+        check(cutoff(self.yaw_lowpass_hz), "yaw_lowpass_hz")?;
+
+        check(self.level_angle_limit > 0.0 && self.level_angle_limit <= 90.0,
+            "level_angle_limit")
+    }
+}
 
-const YAW_LOWPASS_HZ: f32 = 100.0;
+// How the cyclic (roll, pitch) sticks are interpreted
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum LevelMode {
+
+    // Sticks command angular rates
+    Acro,
+
+    // Sticks command angles, self-leveling when centered
+    Angle,
+
+    // Self-leveling that fades out as the sticks approach full deflection
+    Horizon
+}
 
 #[derive(Clone)]
 pub struct Pid { 
+    config: AnglePidConfig,
+    level_mode: LevelMode,
+    rates: RateProfile,
     k_rate_p: f32,
     k_rate_i: f32,
     k_rate_d: f32,
     k_rate_f: f32,
     k_level_p: f32,
-    usec_prev: u32,
+    dt: f32,
+    dyn_lpf_usec_prev: u32,
     roll : CyclicAxis,
     pitch : CyclicAxis,
     yaw: Axis,
@@ -87,21 +204,31 @@ pub fn make(
     k_rate_i: f32,
     k_rate_d: f32,
     k_rate_f: f32,
-    k_level_p: f32) -> Pid {
-
-        Pid {
-            k_rate_p: k_rate_p,
-            k_rate_i: k_rate_i,
-            k_rate_d: k_rate_d,
-            k_rate_f: k_rate_f,
-            k_level_p: k_level_p,
-            usec_prev: 0,
-            roll : make_cyclic_axis(),
-            pitch : make_cyclic_axis(),
+    k_level_p: f32,
+    loop_config: &LoopConfig,
+    config: &AnglePidConfig) -> Result<Pid, InvalidConfig> {
+
+        config.validate(loop_config)?;
+
+        let dt = loop_config.dt();
+
+        Ok(Pid {
+            config: *config,
+            level_mode: LevelMode::Angle,
+            rates: rates::DEFAULT_PROFILE,
+            k_rate_p,
+            k_rate_i,
+            k_rate_d,
+            k_rate_f,
+            k_level_p,
+            dt,
+            dyn_lpf_usec_prev: 0,
+            roll : make_cyclic_axis(config, dt, &rates::DEFAULT_PROFILE.roll),
+            pitch : make_cyclic_axis(config, dt, &rates::DEFAULT_PROFILE.pitch),
             yaw: make_axis(),
             dyn_lpf_previous_quantized_throttle: 0, 
-            pterm_yaw_lpf : filters::make_pt1(YAW_LOWPASS_HZ)
-        }
+            pterm_yaw_lpf : filters::make_pt1(config.yaw_lowpass_hz, dt)
+        })
 } 
 
 pub fn get_demands(
@@ -111,19 +238,24 @@ pub fn get_demands(
     vstate: &VehicleState,
     reset: &bool) -> Demands {
 
-        let d_usec = *usec - pid.usec_prev;
-        pid.usec_prev = *usec;
+        let roll_demand  = pid.rates.roll.apply(demands.roll);
+        let pitch_demand = pid.rates.pitch.apply(demands.pitch);
+        let yaw_demand   = pid.rates.yaw.apply(demands.yaw);
 
-        let roll_demand  = rescale_axis(demands.roll);
-        let pitch_demand = rescale_axis(demands.pitch);
-        let yaw_demand   = rescale_axis(demands.yaw);
+        let max_velocity = pid.config.rate_accel_limit * 100.0 * pid.dt;
 
-        let max_velocity = RATE_ACCEL_LIMIT * 100.0 * DT;
+        // Level strength in horizon mode falls off with the larger cyclic
+        // stick deflection
+        let horizon_strength =
+            constrain_f(1.0 - demands.roll.abs().max(demands.pitch.abs()), 0.0, 1.0);
 
         let roll = 
             update_cyclic(
                 &mut pid.roll,
+                &pid.config,
+                pid.level_mode,
                 pid.k_level_p,
+                horizon_strength,
                 pid.k_rate_p,
                 pid.k_rate_i,
                 pid.k_rate_d,
@@ -131,13 +263,17 @@ pub fn get_demands(
                 roll_demand,
                 vstate.phi,
                 vstate.dphi,
-                max_velocity);
+                max_velocity,
+                pid.dt);
 
 
         let pitch = 
             update_cyclic(
                 &mut pid.pitch,
+                &pid.config,
+                pid.level_mode,
                 pid.k_level_p,
+                horizon_strength,
                 pid.k_rate_p,
                 pid.k_rate_i,
                 pid.k_rate_d,
@@ -145,21 +281,27 @@ pub fn get_demands(
                 pitch_demand,
                 vstate.theta,
                 vstate.dtheta,
-                max_velocity);
+                max_velocity,
+                pid.dt);
 
         let yaw = update_yaw(
             &mut pid.yaw,
-            pid.pterm_yaw_lpf,
+            &mut pid.pterm_yaw_lpf,
+            &pid.config,
             pid.k_rate_p,
             pid.k_rate_i,
             yaw_demand,
-            vstate.dpsi);
+            vstate.dpsi,
+            pid.dt);
 
         pid.roll.axis.integral = if *reset { 0.0 } else { pid.roll.axis.integral };
         pid.pitch.axis.integral = if *reset { 0.0 } else { pid.pitch.axis.integral };
         pid.yaw.integral = if *reset { 0.0 } else { pid.yaw.integral };
 
-        if d_usec >= DYN_LPF_THROTTLE_UPDATE_DELAY_US {
+        // Limit dynamic-filter updates to one every few milliseconds
+        if usec.wrapping_sub(pid.dyn_lpf_usec_prev) >= DYN_LPF_THROTTLE_UPDATE_DELAY_US {
+
+            pid.dyn_lpf_usec_prev = *usec;
 
             // Quantize the throttle to reduce the number of filter updates
             let quantized_throttle = (demands.throttle * DYN_LPF_THROTTLE_STEPS) as i32; 
@@ -171,9 +313,9 @@ pub fn get_demands(
                 let dyn_lpf_throttle = (quantized_throttle as f32) / DYN_LPF_THROTTLE_STEPS;
 
                 let cutoff_freq = dyn_lpf_cutoff_freq(dyn_lpf_throttle,
-                    DTERM_LPF1_DYN_MIN_HZ,
-                    DTERM_LPF1_DYN_MAX_HZ,
-                    DYN_LPF_CURVE_EXPO);
+                    pid.config.dterm_lpf1_dyn_min_hz,
+                    pid.config.dterm_lpf1_dyn_max_hz,
+                    pid.config.dyn_lpf_curve_expo);
 
                 init_lpf1(&mut pid.roll, cutoff_freq);
                 init_lpf1(&mut pid.pitch, cutoff_freq);
@@ -184,9 +326,9 @@ pub fn get_demands(
 
         Demands { 
             throttle : demands.throttle,
-            roll : constrain_output(roll, LIMIT_CYCLIC),
-            pitch : constrain_output(pitch, LIMIT_CYCLIC),
-            yaw : constrain_output(yaw, LIMIT_YAW)
+            roll : constrain_output(roll, pid.config.limit_cyclic),
+            pitch : constrain_output(pitch, pid.config.limit_cyclic),
+            yaw : constrain_output(yaw, pid.config.limit_yaw)
         }
     }
 
@@ -201,17 +343,25 @@ struct Axis {
 struct CyclicAxis {
 
     axis: Axis,
+    dterm_notch : Option<filters::Biquad>,
     dterm_lpf1 : filters::Pt1,
     dterm_lpf2 : filters::Pt1,
     d_min_lpf: filters::Pt2,
     d_min_range: filters::Pt2,
     windup_lpf: filters::Pt1,
-    previous_dterm: f32
+    previous_dterm: f32,
+
+    // Rate at full stick, which the feedforward limit is a percentage of
+    max_rate: f32
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_cyclic(
     cyclic_axis: &mut CyclicAxis,
+    config: &AnglePidConfig,
+    level_mode: LevelMode,
     k_level_p: f32,
+    horizon_strength: f32,
     k_rate_p: f32,
     k_rate_i: f32,
     k_rate_d: f32,
@@ -219,8 +369,11 @@ fn update_cyclic(
     demand: f32,
     angle: f32,
     angvel: f32,
-    max_velocity: f32) -> f32
+    max_velocity: f32,
+    dt: f32) -> f32
 {
+    let max_rate = cyclic_axis.max_rate;
+
     let axis: &mut Axis = &mut cyclic_axis.axis;
 
     let current_setpoint =
@@ -229,17 +382,24 @@ fn update_cyclic(
         {acceleration_limit(axis, demand, max_velocity)}
         else {demand};
 
-    let new_setpoint = level_pid(k_level_p, current_setpoint, angle);
+    let new_setpoint =
+        level_pid(
+            level_mode,
+            k_level_p,
+            horizon_strength,
+            config.level_angle_limit,
+            current_setpoint,
+            angle);
 
     // -----calculate error rate
     let error_rate = new_setpoint - angvel;
 
-    let setpoint_lpf = filters::apply_pt1(cyclic_axis.windup_lpf, current_setpoint);
+    let setpoint_lpf = cyclic_axis.windup_lpf.apply(current_setpoint);
 
     let setpoint_hpf = (current_setpoint - setpoint_lpf).abs();
 
     let iterm_relax_factor =
-        (1.0 - setpoint_hpf / ITERM_RELAX_SETPOINT_THRESHOLD).max(0.0);
+        (1.0 - setpoint_hpf / config.iterm_relax_setpoint_threshold).max(0.0);
 
     let is_decreasing_i =
         ((axis.integral > 0.0) && (error_rate < 0.0)) ||
@@ -248,20 +408,23 @@ fn update_cyclic(
     // Was applyItermRelax in original
     let iterm_error_rate = error_rate * (if !is_decreasing_i  {iterm_relax_factor} else {1.0} );
 
-    let frequency = 1.0 / DT;
+    let frequency = 1.0 / dt;
 
     // Calculate P component --------------------------------------------------
     let pterm = k_rate_p * error_rate;
 
     // Calculate I component --------------------------------------------------
-    axis.integral = constrain_f(axis.integral + (k_rate_i * DT) * iterm_error_rate,
-    -ITERM_LIMIT, ITERM_LIMIT);
+    axis.integral = constrain_f(axis.integral + (k_rate_i * dt) * iterm_error_rate,
+    -config.iterm_limit, config.iterm_limit);
 
     // Calculate D component --------------------------------------------------
 
-    let dterm = filters::apply_pt1(
-        cyclic_axis.dterm_lpf2, 
-        filters::apply_pt1(cyclic_axis.dterm_lpf1, angvel));
+    let notched = match cyclic_axis.dterm_notch {
+        Some(ref mut notch) => notch.apply(angvel),
+        None => angvel
+    };
+
+    let dterm = cyclic_axis.dterm_lpf2.apply(cyclic_axis.dterm_lpf1.apply(notched));
 
     // Divide rate change by dT to get differential (ie dr/dt).
     // dT is fixed and calculated from the target PID loop time
@@ -272,24 +435,26 @@ fn update_cyclic(
 
     let pre_t_pa_d = k_rate_d * delta;
 
-    let d_min_percent = if D_MIN > 0.0 && D_MIN < k_rate_d { D_MIN / k_rate_d } else { 0.0 };
+    let d_min = config.d_min;
+
+    let d_min_percent = if d_min > 0.0 && d_min < k_rate_d { d_min / k_rate_d } else { 0.0 };
 
     let demand_delta: f32 = 0.0;
 
-    let d_min_gyro_gain = D_MIN_GAIN * D_MIN_GAIN_FACTOR / D_MIN_LOWPASS_HZ;
+    let d_min_gyro_gain = config.d_min_gain * D_MIN_GAIN_FACTOR / config.d_min_lowpass_hz;
 
-    let d_min_gyro_factor = (filters::apply_pt2(cyclic_axis.d_min_range, delta)).abs() * d_min_gyro_gain;
+    let d_min_gyro_factor = cyclic_axis.d_min_range.apply(delta).abs() * d_min_gyro_gain;
 
     let d_min_setpoint_gain =
-        D_MIN_GAIN * D_MIN_SETPOINT_GAIN_FACTOR * D_MIN_ADVANCE * frequency /
-        (100.0 * D_MIN_LOWPASS_HZ);
+        config.d_min_gain * D_MIN_SETPOINT_GAIN_FACTOR * config.d_min_advance * frequency /
+        (100.0 * config.d_min_lowpass_hz);
 
     let d_min_setpoint_factor = (demand_delta).abs() * d_min_setpoint_gain;
 
     let d_min_factor = 
         d_min_percent + (1.0 - d_min_percent) * d_min_gyro_factor.max(d_min_setpoint_factor);
 
-    let d_min_factor_filtered = filters::apply_pt2(cyclic_axis.d_min_lpf, d_min_factor);
+    let d_min_factor_filtered = cyclic_axis.d_min_lpf.apply(d_min_factor);
 
     let d_min_factor = d_min_factor_filtered.min(1.0);
 
@@ -305,10 +470,7 @@ fn update_cyclic(
     // feedforward.c when new RC data arrives 
     let feed_forward = k_rate_f * demand_delta * frequency;
 
-    let feedforward_max_rate: f32 = 670.0;
-
-    let feedforward_max_rate_limit =
-        feedforward_max_rate * FEEDFORWARD_MAX_RATE_LIMIT * 0.01;
+    let feedforward_max_rate_limit = max_rate * config.feedforward_max_rate_limit * 0.01;
 
     let fterm = if feedforward_max_rate_limit != 0.0 {
         apply_feeedforward_limit(
@@ -325,22 +487,25 @@ fn update_cyclic(
 } // update_cyclic
 
 
+#[allow(clippy::too_many_arguments)]
 fn update_yaw(
     axis: &mut Axis,
-    pterm_lpf: filters::Pt1,
+    pterm_lpf: &mut filters::Pt1,
+    config: &AnglePidConfig,
     kp: f32,
     ki: f32,
     demand: f32,
-    angvel: f32) -> f32 {
+    angvel: f32,
+    dt: f32) -> f32 {
 
         // gradually scale back integration when above windup point
-        let iterm_windup_point_inv = 1.0 / (1.0 - (ITERM_WINDUP_POINT_PERCENT / 100.0));
+        let iterm_windup_point_inv = 1.0 / (1.0 - (config.iterm_windup_point_percent / 100.0));
 
-        let dyn_ci = DT * (if iterm_windup_point_inv > 1.0
+        let dyn_ci = dt * (if iterm_windup_point_inv > 1.0
             {constrain_f(iterm_windup_point_inv, 0.0, 1.0)}
             else {1.0});
 
-        let max_velocity = YAW_RATE_ACCEL_LIMIT * 100.0 * DT; 
+        let max_velocity = config.yaw_rate_accel_limit * 100.0 * dt; 
 
         let current_setpoint =
             if max_velocity > 0.0 {acceleration_limit(axis, demand, max_velocity)} else {demand};
@@ -348,26 +513,34 @@ fn update_yaw(
         let error_rate = current_setpoint - angvel;
 
         // -----calculate P component
-        let pterm = filters::apply_pt1(pterm_lpf, kp * error_rate);
+        let pterm = pterm_lpf.apply(kp * error_rate);
 
         // -----calculate I component, constraining windup
         axis.integral =
-            constrain_f(axis.integral + (ki * dyn_ci) * error_rate, -ITERM_LIMIT, ITERM_LIMIT);
+            constrain_f(axis.integral + (ki * dyn_ci) * error_rate,
+                -config.iterm_limit, config.iterm_limit);
 
         pterm + axis.integral
     }
 
 
-fn make_cyclic_axis() -> CyclicAxis {
+fn make_cyclic_axis(config: &AnglePidConfig, dt: f32, rates: &Rates) -> CyclicAxis {
 
     CyclicAxis {
         axis: make_axis(),
-        dterm_lpf1 : filters::make_pt1(DTERM_LPF1_DYN_MIN_HZ),
-        dterm_lpf2 : filters::make_pt1(DTERM_LPF2_HZ),
-        d_min_lpf: filters::make_pt2(D_MIN_LOWPASS_HZ),
-        d_min_range: filters::make_pt2(D_MIN_RANGE_HZ),
-        windup_lpf: filters::make_pt1(ITERM_RELAX_CUTOFF),
-        previous_dterm: 0.0 }
+        dterm_notch : if config.dterm_notch_hz > 0.0 {
+            Some(filters::make_biquad_notch(
+                    config.dterm_notch_hz, config.dterm_notch_cutoff_hz, dt))
+        } else {
+            None
+        },
+        dterm_lpf1 : filters::make_pt1(config.dterm_lpf1_dyn_min_hz, dt),
+        dterm_lpf2 : filters::make_pt1(config.dterm_lpf2_hz, dt),
+        d_min_lpf: filters::make_pt2(config.d_min_lowpass_hz, dt),
+        d_min_range: filters::make_pt2(config.d_min_range_hz, dt),
+        windup_lpf: filters::make_pt1(config.iterm_relax_cutoff_hz, dt),
+        previous_dterm: 0.0,
+        max_rate: rates.apply(1.0) }
 }
 
 fn make_axis() -> Axis {
@@ -375,30 +548,40 @@ fn make_axis() -> Axis {
     Axis { previous_setpoint: 0.0, integral: 0.0 }
 }
 
-// [-1,+1] => [-670,+670] with nonlinearity
-fn rescale_axis(command: f32) -> f32 {
+pub fn set_level_mode(pid: &mut Pid, level_mode: LevelMode) {
 
-    const CTR: f32 = 0.104;
+    pid.level_mode = level_mode;
+}
+
+pub fn set_rates(pid: &mut Pid, rates: &RateProfile) {
 
-    let expof = command * command.abs();
-    let angle_rate = command * CTR + (1.0 - CTR) * expof;
+    pid.rates = *rates;
 
-    670.0 * angle_rate
+    pid.roll.max_rate = rates.roll.apply(1.0);
+    pid.pitch.max_rate = rates.pitch.apply(1.0);
 }
 
-fn level_pid(k_level_p: f32, current_setpoint: f32, current_angle: f32) -> f32
+fn level_pid(
+    level_mode: LevelMode,
+    k_level_p: f32,
+    horizon_strength: f32,
+    level_angle_limit: f32,
+    current_setpoint: f32,
+    current_angle: f32) -> f32
 {
     // calculate error angle and limit the angle to the max inclination
     // rcDeflection in [-1.0, 1.0]
 
-    const LEVEL_ANGLE_LIMIT: f32 = 45.0;
-
-    let angle = constrain_f(LEVEL_ANGLE_LIMIT * current_setpoint,
-        -LEVEL_ANGLE_LIMIT, LEVEL_ANGLE_LIMIT);
+    let angle = constrain_f(level_angle_limit * current_setpoint,
+        -level_angle_limit, level_angle_limit);
 
     let angle_error = angle - (current_angle / 10.0);
 
-    if k_level_p > 0.0  {angle_error * k_level_p } else {current_setpoint}
+    match level_mode {
+        LevelMode::Acro => current_setpoint,
+        LevelMode::Angle => angle_error * k_level_p,
+        LevelMode::Horizon => current_setpoint + angle_error * k_level_p * horizon_strength
+    }
 }
 
 fn acceleration_limit(axis: &mut Axis, current_setpoint: f32, max_velocity: f32) -> f32 {
@@ -441,7 +624,7 @@ fn apply_feeedforward_limit(
 
 fn init_lpf1(cyclic_axis: &mut CyclicAxis, cutoff_freq: f32) {
 
-    filters::adjust_pt1_gain(cyclic_axis.dterm_lpf1, cutoff_freq);
+    cyclic_axis.dterm_lpf1.set_cutoff(cutoff_freq);
 }
 
 fn dyn_lpf_cutoff_freq(throttle: f32, dyn_lpf_min: f32, dyn_lpf_max: f32, expo: f32) -> f32 {
@@ -0,0 +1,191 @@
+/*
+   Stick rate curves, after Betaflight's rc.c
+
+   Each curve maps a stick demand in [-1,+1] to an angular rate setpoint in
+   degrees per second.  Parameters are in the units shown by the Betaflight
+   configurator's rates tab.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::pids::InvalidConfig;
+use crate::pids::check;
+use crate::utils::constrain_f;
+
+// Degrees per second
+pub const SETPOINT_RATE_LIMIT: f32 = 1998.0;
+
+// Extra RC rate per unit above 2.0, in Betaflight rates
+const RC_RATE_INCREMENTAL: f32 = 14.54;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rates {
+
+    // RC rate (e.g. 1.0), super rate (e.g. 0.7), expo in [0,1]
+    Betaflight { rc_rate: f32, super_rate: f32, expo: f32 },
+
+    // Center sensitivity and max rate in degrees per second, expo in [0,1]
+    Actual { center_sensitivity: f32, max_rate: f32, expo: f32 },
+
+    // RC rate, rate, RC curve in [0,1]
+    Kiss { rc_rate: f32, rate: f32, rc_curve: f32 },
+
+    // RC rate, max rate in degrees per second, expo in [0,1]; Betaflight's
+    // quick_rates_rc_expo is off
+    Quick { rc_rate: f32, max_rate: f32, expo: f32 }
+}
+
+impl Rates {
+
+    // Comparisons fail for NaN, so NaN fields are rejected too
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+
+        match *self {
+
+            Rates::Betaflight { rc_rate, super_rate, expo } => {
+                check(rc_rate > 0.0, "rc_rate")?;
+                check((0.0..1.0).contains(&super_rate), "super_rate")?;
+                check((0.0..=1.0).contains(&expo), "expo")
+            }
+
+            Rates::Actual { center_sensitivity, max_rate, expo } => {
+                check(center_sensitivity > 0.0, "center_sensitivity")?;
+                check(max_rate > 0.0, "max_rate")?;
+                check((0.0..=1.0).contains(&expo), "expo")
+            }
+
+            Rates::Kiss { rc_rate, rate, rc_curve } => {
+                check(rc_rate > 0.0, "rc_rate")?;
+                check((0.0..1.0).contains(&rate), "rate")?;
+                check((0.0..=1.0).contains(&rc_curve), "rc_curve")
+            }
+
+            // The max rate is in degrees per second, and the RC rate in
+            // units of 200 degrees per second
+            Rates::Quick { rc_rate, max_rate, expo } => {
+                check(rc_rate > 0.0, "rc_rate")?;
+                check(max_rate > 0.0 && max_rate >= rc_rate * 200.0, "max_rate")?;
+                check((0.0..=1.0).contains(&expo), "expo")
+            }
+        }
+    }
+
+    // [-1,+1] => degrees per second
+    pub fn apply(&self, command: f32) -> f32 {
+
+        let command = constrain_f(command, -1.0, 1.0);
+        let command_abs = command.abs();
+
+        let rate = match *self {
+
+            Rates::Betaflight { rc_rate, super_rate, expo } => {
+
+                let command = command * power3(command_abs) * expo + command * (1.0 - expo);
+
+                let rc_rate = if rc_rate > 2.0 {
+                    rc_rate + RC_RATE_INCREMENTAL * (rc_rate - 2.0)
+                } else {
+                    rc_rate
+                };
+
+                200.0 * rc_rate * command * super_factor(command_abs * super_rate)
+            }
+
+            Rates::Actual { center_sensitivity, max_rate, expo } => {
+
+                let expof =
+                    command_abs * (power5(command) * expo + command * (1.0 - expo));
+
+                let stick_movement = (max_rate - center_sensitivity).max(0.0);
+
+                command * center_sensitivity + stick_movement * expof
+            }
+
+            Rates::Kiss { rc_rate, rate, rc_curve } => {
+
+                let curve = (power3(command) * rc_curve + command * (1.0 - rc_curve))
+                    * rc_rate / 10.0;
+
+                2000.0 * super_factor(command_abs * rate) * curve
+            }
+
+            Rates::Quick { rc_rate, max_rate, expo } => {
+
+                let rc_rate = rc_rate * 200.0;
+                let max_rate = max_rate.max(rc_rate);
+
+                let super_factor_config = (max_rate / rc_rate - 1.0) / (max_rate / rc_rate);
+
+                let curve = power3(command_abs) * expo + command_abs * (1.0 - expo);
+
+                command * rc_rate * super_factor(curve * super_factor_config)
+            }
+        };
+
+        constrain_f(rate, -SETPOINT_RATE_LIMIT, SETPOINT_RATE_LIMIT)
+    }
+}
+
+// Roll, pitch, yaw
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateProfile {
+
+    pub roll: Rates,
+    pub pitch: Rates,
+    pub yaw: Rates
+}
+
+impl RateProfile {
+
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+
+        self.roll.validate()?;
+        self.pitch.validate()?;
+        self.yaw.validate()
+    }
+}
+
+// The curve the angle controller has always used: 670 deg/sec, with
+// 10.4% linear center
+pub const DEFAULT_RATES: Rates = Rates::Actual {
+    center_sensitivity: 0.104 * 670.0,
+    max_rate: 670.0,
+    expo: 0.0
+};
+
+pub const DEFAULT_PROFILE: RateProfile = RateProfile {
+    roll: DEFAULT_RATES,
+    pitch: DEFAULT_RATES,
+    yaw: DEFAULT_RATES
+};
+
+fn power3(x: f32) -> f32 {
+
+    x * x * x
+}
+
+fn power5(x: f32) -> f32 {
+
+    x * x * x * x * x
+}
+
+fn super_factor(x: f32) -> f32 {
+
+    1.0 / constrain_f(1.0 - x, 0.01, 1.0)
+}
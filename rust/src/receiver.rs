@@ -0,0 +1,248 @@
+/*
+   Receiver channel handling, after the C++ ReceiverTask
+
+   Raw channel values from any protocol (SBUS, DSMX, ...) are converted to
+   the [1000,2000] range, then to stick demands: throttle through an expo
+   lookup table into [0,1], and roll, pitch, yaw into [-1,+1].
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::Demands;
+use crate::utils::constrain_f;
+
+pub mod crsf;
+pub mod dsmx;
+pub mod sbus;
+
+// Throttle, roll, pitch, yaw, aux1, aux2
+pub const CHANNEL_COUNT: usize = 6;
+
+pub const AUX_COUNT: usize = 2;
+
+const TIMEOUT_USEC: u32 = 30000;
+
+const THROTTLE_LOOKUP_TABLE_SIZE: usize = 12;
+const THROTTLE_EXPO8: f32 = 0.0;
+const THROTTLE_MID8: f32 = 50.0;
+
+// Throttle below this is treated as zero stick
+const THROTTLE_DOWN: f32 = 1050.0;
+
+// Three-position switch thresholds
+const AUX_LOW_MAX: f32 = 1300.0;
+const AUX_HIGH_MIN: f32 = 1700.0;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum AuxSwitch {
+
+    Low,
+    Mid,
+    High
+}
+
+#[derive(Clone)]
+pub struct Receiver {
+
+    // Converted to [1000,2000]
+    channels: [f32; CHANNEL_COUNT],
+
+    got_new_data: bool,
+    lost_signal: bool,
+    // None until the first frame arrives
+    last_signaled_usec: Option<u32>,
+    lookup_throttle_rc: [i16; THROTTLE_LOOKUP_TABLE_SIZE],
+
+    // Last good roll, pitch, yaw demands
+    axes: [f32; 3]
+}
+
+impl Receiver {
+
+    // Called whenever the receiver gets a new frame, with the range of raw
+    // channel values for its protocol
+    pub fn set_values(
+        &mut self,
+        channels: &[u16; CHANNEL_COUNT],
+        usec: u32,
+        lost_signal: bool,
+        src_min: u16,
+        src_max: u16) {
+
+        for (channel, &value) in self.channels.iter_mut().zip(channels) {
+            *channel = convert(value, src_min, src_max);
+        }
+
+        self.lost_signal = lost_signal;
+        self.last_signaled_usec = Some(usec);
+        self.got_new_data = true;
+    }
+
+    // Corresponds to C++ ReceiverTask::modifyDemands(); roll, pitch and yaw
+    // keep their last values until a new frame arrives
+    pub fn get_demands(&mut self) -> Demands {
+
+        // [1050,2000] => [0,1000] => expo
+        let tmp = self.raw_throttle().clamp(THROTTLE_DOWN, 2000.0) as i32;
+        let tmp2 = (tmp - THROTTLE_DOWN as i32) * 1000 / 950;
+        let command_throttle = self.lookup_throttle(tmp2);
+
+        if self.got_new_data {
+
+            self.axes = [
+                rescale_command(self.raw_roll(), 1.0),
+                rescale_command(self.raw_pitch(), 1.0),
+                rescale_command(self.raw_yaw(), -1.0)
+            ];
+        }
+
+        self.got_new_data = false;
+
+        Demands {
+            throttle: constrain_f((command_throttle - 1000.0) / 1000.0, 0.0, 1.0),
+            roll: self.axes[0],
+            pitch: self.axes[1],
+            yaw: self.axes[2]
+        }
+    }
+
+    pub fn have_signal(&self, usec: u32) -> bool {
+
+        match self.last_signaled_usec {
+            Some(last) => !self.lost_signal && usec.wrapping_sub(last) < TIMEOUT_USEC,
+            None => false
+        }
+    }
+
+    pub fn throttle_is_down(&self) -> bool {
+
+        self.raw_throttle() < THROTTLE_DOWN
+    }
+
+    pub fn raw_throttle(&self) -> f32 {
+
+        self.channels[0]
+    }
+
+    pub fn raw_roll(&self) -> f32 {
+
+        self.channels[1]
+    }
+
+    pub fn raw_pitch(&self) -> f32 {
+
+        self.channels[2]
+    }
+
+    pub fn raw_yaw(&self) -> f32 {
+
+        self.channels[3]
+    }
+
+    // Raw value in [1000,2000] of an aux channel (0=aux1, 1=aux2)
+    pub fn aux(&self, index: usize) -> f32 {
+
+        self.channels[4 + index]
+    }
+
+    pub fn aux_switch(&self, index: usize) -> AuxSwitch {
+
+        let value = self.aux(index);
+
+        if value < AUX_LOW_MAX {
+            AuxSwitch::Low
+        } else if value > AUX_HIGH_MIN {
+            AuxSwitch::High
+        } else {
+            AuxSwitch::Mid
+        }
+    }
+
+    // [0,1000] => expo => [1000,2000]
+    fn lookup_throttle(&self, tmp: i32) -> f32 {
+
+        let table = &self.lookup_throttle_rc;
+
+        let tmp3 = (tmp / 100) as usize;
+
+        let lo = table[tmp3] as i32;
+        let hi = table[tmp3 + 1] as i32;
+
+        (lo + (tmp - (tmp3 as i32) * 100) * (hi - lo) / 100) as f32
+    }
+}
+
+pub fn make() -> Receiver {
+
+    Receiver {
+        channels: [0.0; CHANNEL_COUNT],
+        got_new_data: false,
+        lost_signal: false,
+        last_signaled_usec: None,
+        lookup_throttle_rc: make_throttle_table(),
+        axes: [0.0; 3]
+    }
+}
+
+fn make_throttle_table() -> [i16; THROTTLE_LOOKUP_TABLE_SIZE] {
+
+    let mut table = [0; THROTTLE_LOOKUP_TABLE_SIZE];
+
+    for (i, entry) in table.iter_mut().enumerate() {
+
+        let tmp2 = 10 * (i as i32) - (THROTTLE_MID8 as i32);
+
+        let y = if tmp2 > 0 {
+            100.0 - THROTTLE_MID8
+        } else if tmp2 < 0 {
+            THROTTLE_MID8
+        } else {
+            1.0
+        } as i32;
+
+        let expo = (THROTTLE_EXPO8 as i32) * (tmp2 * tmp2) / (y * y);
+
+        let value = 10.0 * THROTTLE_MID8
+            + (tmp2 as f32) * (100.0 - THROTTLE_EXPO8 + expo as f32) / 10.0;
+
+        *entry = 1000 + value as i16;
+    }
+
+    table
+}
+
+// An empty source range gives the neutral value, rather than NaN or inf
+fn convert(value: u16, src_min: u16, src_max: u16) -> f32 {
+
+    if src_min == src_max {
+        return 1500.0;
+    }
+
+    let (value, src_min, src_max) = (value as f32, src_min as f32, src_max as f32);
+
+    1000.0 + 1000.0 * (value - src_min) / (src_max - src_min)
+}
+
+// [1000,2000] => [-1,+1]
+fn rescale_command(raw: f32, sgn: f32) -> f32 {
+
+    let tmp = (raw - 1500.0).abs().min(500.0);
+    let cmd = tmp * sgn;
+    let command = if raw < 1500.0 { -cmd } else { cmd };
+
+    command / 500.0
+}
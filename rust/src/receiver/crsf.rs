@@ -0,0 +1,283 @@
+/*
+   Crossfire (CRSF) receiver protocol, as spoken by ExpressLRS receivers
+
+   Frames are an address byte, a length byte (covering the type, payload and
+   CRC), a type byte, the payload, and a CRC8 DVB-S2 of the type and payload.
+   We decode RC channels and link statistics from the receiver, and encode
+   battery, attitude and flight-mode telemetry for it to send to the radio.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::VehicleState;
+use crate::msp::crc8_dvb_s2;
+use crate::utils::deg2rad;
+
+use super::CHANNEL_COUNT;
+use super::Receiver;
+use super::sbus::unpack_channels;
+
+pub const MAX_FRAME_SIZE: usize = 64;
+
+pub const NUM_CHANNELS: usize = 16;
+
+// Range of channel values, corresponding to 988..2012 usec
+pub const CHANNEL_MIN: u16 = 172;
+pub const CHANNEL_MAX: u16 = 1811;
+
+// Address of the flight controller, also used as the sync byte
+pub const ADDRESS_FLIGHT_CONTROLLER: u8 = 0xC8;
+
+pub const FRAMETYPE_BATTERY_SENSOR: u8 = 0x08;
+pub const FRAMETYPE_LINK_STATISTICS: u8 = 0x14;
+pub const FRAMETYPE_RC_CHANNELS_PACKED: u8 = 0x16;
+pub const FRAMETYPE_ATTITUDE: u8 = 0x1E;
+pub const FRAMETYPE_FLIGHT_MODE: u8 = 0x21;
+
+const RC_CHANNELS_PAYLOAD_SIZE: usize = 22;
+const LINK_STATISTICS_PAYLOAD_SIZE: usize = 10;
+
+// Longest flight-mode name we send, not counting the terminating null
+pub const FLIGHT_MODE_MAX_LENGTH: usize = 15;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct RcChannels {
+
+    pub channels: [u16; NUM_CHANNELS]
+}
+
+impl RcChannels {
+
+    pub fn set_receiver(&self, receiver: &mut Receiver, usec: u32) {
+
+        let mut channels = [0; CHANNEL_COUNT];
+
+        channels.copy_from_slice(&self.channels[..CHANNEL_COUNT]);
+
+        receiver.set_values(&channels, usec, false, CHANNEL_MIN, CHANNEL_MAX);
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct LinkStatistics {
+
+    // Received signal strength in -dBm, per antenna
+    pub uplink_rssi_1: u8,
+    pub uplink_rssi_2: u8,
+
+    // Percentage of packets received
+    pub uplink_link_quality: u8,
+
+    // dB
+    pub uplink_snr: i8,
+
+    pub active_antenna: u8,
+    pub rf_mode: u8,
+    pub uplink_tx_power: u8,
+    pub downlink_rssi: u8,
+    pub downlink_link_quality: u8,
+    pub downlink_snr: i8
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Frame {
+
+    RcChannels(RcChannels),
+    LinkStatistics(LinkStatistics)
+}
+
+#[derive(Clone)]
+pub struct Decoder {
+
+    buf: [u8; MAX_FRAME_SIZE],
+    index: usize
+}
+
+impl Decoder {
+
+    // Consumes one byte, returning the frame once all of it has arrived
+    // with a valid CRC; frames of other types are skipped
+    pub fn parse(&mut self, c: u8) -> Option<Frame> {
+
+        if self.index == 0 && c != ADDRESS_FLIGHT_CONTROLLER {
+            return None;
+        }
+
+        // Length covers type, payload and CRC
+        if self.index == 1 && !(2..=(MAX_FRAME_SIZE as u8 - 2)).contains(&c) {
+            self.index = 0;
+            return None;
+        }
+
+        self.buf[self.index] = c;
+        self.index += 1;
+
+        if self.index < 2 || self.index < (self.buf[1] as usize) + 2 {
+            return None;
+        }
+
+        self.index = 0;
+
+        let size = self.buf[1] as usize;
+
+        let body = &self.buf[2..size + 1];
+
+        if body.iter().fold(0, |crc, &b| crc8_dvb_s2(crc, b)) != self.buf[size + 1] {
+            return None;
+        }
+
+        decode(body[0], &body[1..])
+    }
+}
+
+pub fn make_decoder() -> Decoder {
+
+    Decoder {
+        buf: [0; MAX_FRAME_SIZE],
+        index: 0
+    }
+}
+
+fn decode(frame_type: u8, payload: &[u8]) -> Option<Frame> {
+
+    match frame_type {
+
+        FRAMETYPE_RC_CHANNELS_PACKED if payload.len() == RC_CHANNELS_PAYLOAD_SIZE => {
+
+            Some(Frame::RcChannels(RcChannels { channels: unpack_channels(payload) }))
+        }
+
+        FRAMETYPE_LINK_STATISTICS if payload.len() == LINK_STATISTICS_PAYLOAD_SIZE => {
+
+            Some(Frame::LinkStatistics(LinkStatistics {
+                uplink_rssi_1: payload[0],
+                uplink_rssi_2: payload[1],
+                uplink_link_quality: payload[2],
+                uplink_snr: payload[3] as i8,
+                active_antenna: payload[4],
+                rf_mode: payload[5],
+                uplink_tx_power: payload[6],
+                downlink_rssi: payload[7],
+                downlink_link_quality: payload[8],
+                downlink_snr: payload[9] as i8
+            }))
+        }
+
+        _ => None
+    }
+}
+
+// Builds one telemetry frame at a time, for writing to the receiver
+#[derive(Clone)]
+pub struct Encoder {
+
+    buf: [u8; MAX_FRAME_SIZE],
+    size: usize
+}
+
+impl Encoder {
+
+    // Voltage in volts, current in amperes, capacity used in milliamp-hours
+    pub fn battery(
+        &mut self,
+        voltage: f32,
+        current: f32,
+        capacity_mah: u32,
+        remaining_percent: u8) -> &[u8] {
+
+        let decivolts = (voltage * 10.0 + 0.5) as u16;
+        let deciamps = (current * 10.0 + 0.5) as u16;
+        let capacity = capacity_mah.min(0x00FF_FFFF).to_be_bytes();
+
+        self.begin(FRAMETYPE_BATTERY_SENSOR);
+
+        self.add(&decivolts.to_be_bytes());
+        self.add(&deciamps.to_be_bytes());
+        self.add(&capacity[1..]);
+        self.add(&[remaining_percent]);
+
+        self.complete()
+    }
+
+    // Pitch, roll and yaw from the vehicle state, in radians * 10000; yaw is
+    // sent in [-180,+180] degrees
+    pub fn attitude(&mut self, vstate: &VehicleState) -> &[u8] {
+
+        let yaw = if vstate.psi > 180.0 { vstate.psi - 360.0 } else { vstate.psi };
+
+        self.begin(FRAMETYPE_ATTITUDE);
+
+        for angle in [vstate.theta, vstate.phi, yaw] {
+            self.add(&to_decimilliradians(angle).to_be_bytes());
+        }
+
+        self.complete()
+    }
+
+    // Null-terminated, truncated to FLIGHT_MODE_MAX_LENGTH bytes
+    pub fn flight_mode(&mut self, mode: &str) -> &[u8] {
+
+        let bytes = mode.as_bytes();
+
+        self.begin(FRAMETYPE_FLIGHT_MODE);
+
+        self.add(&bytes[..bytes.len().min(FLIGHT_MODE_MAX_LENGTH)]);
+        self.add(&[0]);
+
+        self.complete()
+    }
+
+    fn begin(&mut self, frame_type: u8) {
+
+        self.buf[0] = ADDRESS_FLIGHT_CONTROLLER;
+        self.buf[2] = frame_type;
+        self.size = 3;
+    }
+
+    fn add(&mut self, bytes: &[u8]) {
+
+        self.buf[self.size..self.size + bytes.len()].copy_from_slice(bytes);
+        self.size += bytes.len();
+    }
+
+    fn complete(&mut self) -> &[u8] {
+
+        // Length covers type, payload and CRC
+        self.buf[1] = (self.size - 1) as u8;
+
+        let crc = self.buf[2..self.size].iter().fold(0, |crc, &b| crc8_dvb_s2(crc, b));
+
+        self.add(&[crc]);
+
+        &self.buf[..self.size]
+    }
+}
+
+pub fn make_encoder() -> Encoder {
+
+    Encoder {
+        buf: [0; MAX_FRAME_SIZE],
+        size: 0
+    }
+}
+
+fn to_decimilliradians(degrees: f32) -> i16 {
+
+    let value = deg2rad(degrees) * 10000.0;
+
+    (if value < 0.0 { value - 0.5 } else { value + 0.5 }) as i16
+}
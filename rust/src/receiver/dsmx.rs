@@ -0,0 +1,204 @@
+/*
+   Spektrum DSM2/DSMX serial (satellite receiver) decoder
+
+   Frames are 16 bytes at 115200 baud: a fade count, a system (protocol)
+   byte, and seven big-endian words, each carrying a channel ID and value.
+   Since frames have no header, we resynchronize on the gap between them,
+   using the arrival time of each byte.  DSMX 11 ms and 22 ms modes split
+   their channels across pairs of frames; each frame updates the channels it
+   carries.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::CHANNEL_COUNT;
+use super::Receiver;
+
+pub const FRAME_SIZE: usize = 16;
+
+pub const MAX_CHANNELS: usize = 12;
+
+// Range of channel values in microseconds, as in the C++ setDsmxValues()
+pub const CHANNEL_MIN: u16 = 988;
+pub const CHANNEL_MAX: u16 = 2011;
+
+// System (protocol) byte values
+pub const SYSTEM_DSM2_1024_22MS: u8 = 0x01;
+pub const SYSTEM_DSM2_2048_11MS: u8 = 0x12;
+pub const SYSTEM_DSMX_2048_22MS: u8 = 0xA2;
+pub const SYSTEM_DSMX_2048_11MS: u8 = 0xB2;
+
+// Bytes arrive well within this of each other inside a frame, and frames
+// are at least 11 msec apart
+const FRAME_GAP_USEC: u32 = 5000;
+
+const TIMEOUT_USEC: u32 = 50000;
+
+const UNUSED_WORD: u16 = 0xFFFF;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Resolution {
+
+    // DSM2 1024/22ms
+    Bits10,
+
+    // DSM2 2048/11ms and DSMX
+    Bits11
+}
+
+#[derive(Clone)]
+pub struct Decoder {
+
+    resolution: Resolution,
+    buf: [u8; FRAME_SIZE],
+    index: usize,
+    prev_byte_usec: Option<u32>,
+    last_frame_usec: Option<u32>,
+    channels: [u16; MAX_CHANNELS],
+    system: u8,
+    prev_fades: Option<u8>,
+    fade_count: u32
+}
+
+impl Decoder {
+
+    // Consumes one byte received at the given time, returning true once a
+    // complete frame has updated the channels
+    pub fn parse(&mut self, c: u8, usec: u32) -> bool {
+
+        // A long enough silence means a new frame is starting
+        if let Some(prev) = self.prev_byte_usec {
+            if usec.wrapping_sub(prev) > FRAME_GAP_USEC {
+                self.index = 0;
+            }
+        }
+
+        self.prev_byte_usec = Some(usec);
+
+        // Extra bytes before the next gap are ignored
+        if self.index == FRAME_SIZE {
+            return false;
+        }
+
+        self.buf[self.index] = c;
+        self.index += 1;
+
+        if self.index < FRAME_SIZE {
+            return false;
+        }
+
+        self.decode();
+
+        self.last_frame_usec = Some(usec);
+
+        true
+    }
+
+    // Channel values in microseconds, in Spektrum order (throttle, aileron,
+    // elevator, rudder, gear, aux1, ...)
+    pub fn channels(&self) -> [u16; MAX_CHANNELS] {
+
+        self.channels
+    }
+
+    // System byte of the last frame
+    pub fn system(&self) -> u8 {
+
+        self.system
+    }
+
+    // Frames missed by the receiver since the first frame we decoded
+    pub fn fades(&self) -> u32 {
+
+        self.fade_count
+    }
+
+    pub fn timed_out(&self, usec: u32) -> bool {
+
+        match self.last_frame_usec {
+            Some(last) => usec.wrapping_sub(last) > TIMEOUT_USEC,
+            None => true
+        }
+    }
+
+    pub fn set_receiver(&self, receiver: &mut Receiver, usec: u32) {
+
+        let mut channels = [0; CHANNEL_COUNT];
+
+        channels.copy_from_slice(&self.channels[..CHANNEL_COUNT]);
+
+        receiver.set_values(
+            &channels,
+            usec,
+            self.timed_out(usec),
+            CHANNEL_MIN,
+            CHANNEL_MAX);
+    }
+
+    fn decode(&mut self) {
+
+        // The receiver's fade counter wraps around
+        let fades = self.buf[0];
+
+        if let Some(prev) = self.prev_fades {
+            self.fade_count += fades.wrapping_sub(prev) as u32;
+        }
+
+        self.prev_fades = Some(fades);
+
+        self.system = self.buf[1];
+
+        let (shift, mask) = match self.resolution {
+            Resolution::Bits10 => (10, 0x03FF),
+            Resolution::Bits11 => (11, 0x07FF)
+        };
+
+        for pair in self.buf[2..].chunks_exact(2) {
+
+            let word = u16::from_be_bytes([pair[0], pair[1]]);
+
+            if word == UNUSED_WORD {
+                continue;
+            }
+
+            let id = ((word >> shift) & 0x0F) as usize;
+            let value = word & mask;
+
+            if id < MAX_CHANNELS {
+                self.channels[id] = match self.resolution {
+                    Resolution::Bits10 => CHANNEL_MIN + value,
+                    Resolution::Bits11 => CHANNEL_MIN + (value >> 1)
+                };
+            }
+        }
+    }
+}
+
+pub fn make_decoder(resolution: Resolution) -> Decoder {
+
+    Decoder {
+        resolution,
+        buf: [0; FRAME_SIZE],
+        index: 0,
+        prev_byte_usec: None,
+        last_frame_usec: None,
+        channels: [CHANNEL_MIN; MAX_CHANNELS],
+        system: 0,
+        prev_fades: None,
+        fade_count: 0
+    }
+}
@@ -0,0 +1,178 @@
+/*
+   SBUS frame decoder
+
+   Frames are 25 bytes at 100 kbaud (8E2, inverted): a header byte, sixteen
+   11-bit channels packed LSB-first into 22 bytes, a flags byte and a footer
+   byte.
+
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::CHANNEL_COUNT;
+use super::Receiver;
+
+pub const FRAME_SIZE: usize = 25;
+
+pub const NUM_CHANNELS: usize = 16;
+
+// Range of channel values, as sent by FrSky receivers
+pub const CHANNEL_MIN: u16 = 172;
+pub const CHANNEL_MAX: u16 = 1811;
+
+const HEADER: u8 = 0x0F;
+const FOOTER: u8 = 0x00;
+
+// SBUS2 footers cycle through 0x04, 0x14, 0x24, 0x34
+const FOOTER2: u8 = 0x04;
+
+const CH17_MASK: u8 = 0x01;
+const CH18_MASK: u8 = 0x02;
+const LOST_FRAME_MASK: u8 = 0x04;
+const FAILSAFE_MASK: u8 = 0x08;
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Frame {
+
+    pub channels: [u16; NUM_CHANNELS],
+
+    // Digital channels
+    pub ch17: bool,
+    pub ch18: bool,
+
+    pub lost_frame: bool,
+    pub failsafe: bool
+}
+
+impl Frame {
+
+    // Passes the first receiver channels on, treating failsafe as loss of
+    // signal.  As in Betaflight, a lost frame alone is skipped, since its
+    // channels are stale.
+    pub fn set_receiver(&self, receiver: &mut Receiver, usec: u32) {
+
+        if self.lost_frame && !self.failsafe {
+            return;
+        }
+
+        let mut channels = [0; CHANNEL_COUNT];
+
+        channels.copy_from_slice(&self.channels[..CHANNEL_COUNT]);
+
+        receiver.set_values(
+            &channels,
+            usec,
+            self.failsafe,
+            CHANNEL_MIN,
+            CHANNEL_MAX);
+    }
+}
+
+#[derive(Clone)]
+pub struct Decoder {
+
+    buf: [u8; FRAME_SIZE],
+    index: usize,
+    prev_byte: u8
+}
+
+impl Decoder {
+
+    // Consumes one byte, returning the frame once all of it has arrived
+    pub fn parse(&mut self, c: u8) -> Option<Frame> {
+
+        let mut frame = None;
+
+        if self.index == 0 {
+
+            // A header only starts a frame if it follows a footer
+            if c == HEADER && is_footer(self.prev_byte) {
+                self.buf[0] = c;
+                self.index = 1;
+            }
+
+        } else {
+
+            self.buf[self.index] = c;
+            self.index += 1;
+
+            if self.index == FRAME_SIZE {
+
+                if is_footer(c) {
+                    frame = Some(decode(&self.buf));
+                }
+
+                self.index = 0;
+            }
+        }
+
+        self.prev_byte = c;
+
+        frame
+    }
+}
+
+pub fn make_decoder() -> Decoder {
+
+    Decoder {
+        buf: [0; FRAME_SIZE],
+        index: 0,
+
+        // So that the first header seen can start a frame
+        prev_byte: FOOTER
+    }
+}
+
+fn is_footer(c: u8) -> bool {
+
+    c == FOOTER || (c & 0x0F) == FOOTER2
+}
+
+fn decode(buf: &[u8; FRAME_SIZE]) -> Frame {
+
+    let flags = buf[23];
+
+    Frame {
+        channels: unpack_channels(&buf[1..23]),
+        ch17: flags & CH17_MASK != 0,
+        ch18: flags & CH18_MASK != 0,
+        lost_frame: flags & LOST_FRAME_MASK != 0,
+        failsafe: flags & FAILSAFE_MASK != 0
+    }
+}
+
+// Sixteen 11-bit channels packed LSB-first into 22 bytes, as also used by
+// CRSF
+pub(super) fn unpack_channels(data: &[u8]) -> [u16; NUM_CHANNELS] {
+
+    let mut channels = [0; NUM_CHANNELS];
+
+    for (k, channel) in channels.iter_mut().enumerate() {
+
+        let bit = 11 * k;
+        let byte = bit / 8;
+        let shift = bit % 8;
+
+        // The last channel ends in the final byte
+        let bits = (data[byte] as u32)
+            | (data[byte + 1] as u32) << 8
+            | (*data.get(byte + 2).unwrap_or(&0) as u32) << 16;
+
+        *channel = ((bits >> shift) & 0x07FF) as u16;
+    }
+
+    channels
+}
@@ -35,5 +35,10 @@ pub fn rescale(val: f32, oldmin: f32, oldmax: f32, newmin: f32, newmax: f32) ->
 
 pub fn rad2deg(rad: f32) -> f32 {
 
-    180.0 * rad / std::f32::consts::PI
+    180.0 * rad / core::f32::consts::PI
+}
+
+pub fn deg2rad(deg: f32) -> f32 {
+
+    deg * core::f32::consts::PI / 180.0
 }
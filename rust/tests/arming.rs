@@ -0,0 +1,217 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::Demands;
+use hackflight::VehicleState;
+use hackflight::arming;
+use hackflight::arming::Arming;
+use hackflight::arming::ArmingStatus;
+use hackflight::failsafe;
+use hackflight::failsafe::Failsafe;
+use hackflight::mixers::quadxbf::QuadXbf;
+use hackflight::mixers::saturation;
+use hackflight::receiver;
+use hackflight::receiver::Receiver;
+use hackflight::step;
+
+const THROTTLE_DOWN: u16 = 1000;
+const THROTTLE_UP: u16 = 1600;
+
+const SWITCH_OFF: u16 = 1000;
+const SWITCH_ON: u16 = 2000;
+
+const QUAD: QuadXbf = QuadXbf { saturation: saturation::DEFAULT_CONFIG };
+
+struct Vehicle {
+
+    receiver: Receiver,
+    failsafe: Failsafe,
+    arming: Arming,
+    vstate: VehicleState,
+    gyro_calibrating: bool
+}
+
+impl Vehicle {
+
+    fn update(&mut self, usec: u32, throttle: u16, switch: u16) -> ArmingStatus {
+
+        let channels = [throttle, 1500, 1500, 1500, switch, 1000];
+
+        self.receiver.set_values(&channels, usec, false, 1000, 2000);
+
+        self.tick(usec)
+    }
+
+    // No new frame
+    fn tick(&mut self, usec: u32) -> ArmingStatus {
+
+        self.failsafe.update(usec, self.receiver.have_signal(usec));
+
+        self.arming.update(
+            usec,
+            &self.receiver,
+            &self.vstate,
+            self.gyro_calibrating,
+            &self.failsafe)
+    }
+}
+
+fn make_vehicle() -> Vehicle {
+
+    Vehicle {
+        receiver: receiver::make(),
+        failsafe: failsafe::make(failsafe::DEFAULT_CONFIG).unwrap(),
+        arming: arming::make(),
+        vstate: VehicleState {
+            x: 0.0, dx: 0.0, y: 0.0, dy: 0.0, z: 0.0, dz: 0.0,
+            phi: 0.0, dphi: 0.0, theta: 0.0, dtheta: 0.0, psi: 0.0, dpsi: 0.0
+        },
+        gyro_calibrating: false
+    }
+}
+
+#[test]
+fn arms_and_disarms_with_switch() {
+
+    let mut vehicle = make_vehicle();
+
+    assert_eq!(vehicle.update(0, THROTTLE_DOWN, SWITCH_OFF), ArmingStatus::Ready);
+    assert_eq!(vehicle.arming.blockers(), 0);
+
+    assert_eq!(vehicle.update(10_000, THROTTLE_DOWN, SWITCH_ON), ArmingStatus::Armed);
+
+    // Throttle up is fine once armed
+    assert_eq!(vehicle.update(20_000, THROTTLE_UP, SWITCH_ON), ArmingStatus::Armed);
+
+    assert_eq!(vehicle.update(30_000, THROTTLE_UP, SWITCH_OFF), ArmingStatus::Unready);
+    assert_eq!(vehicle.arming.blockers(), arming::BLOCKED_THROTTLE_UP);
+}
+
+#[test]
+fn switch_must_start_off() {
+
+    let mut vehicle = make_vehicle();
+
+    assert_eq!(vehicle.update(0, THROTTLE_DOWN, SWITCH_ON), ArmingStatus::Unready);
+    assert_eq!(vehicle.arming.blockers(), arming::BLOCKED_SWITCH_NOT_OFF);
+
+    assert_eq!(vehicle.update(10_000, THROTTLE_DOWN, SWITCH_OFF), ArmingStatus::Ready);
+    assert_eq!(vehicle.update(20_000, THROTTLE_DOWN, SWITCH_ON), ArmingStatus::Armed);
+}
+
+#[test]
+fn switch_held_on_while_unready_does_not_arm() {
+
+    let mut vehicle = make_vehicle();
+
+    vehicle.update(0, THROTTLE_DOWN, SWITCH_OFF);
+
+    assert_eq!(vehicle.update(10_000, THROTTLE_UP, SWITCH_ON), ArmingStatus::Unready);
+
+    // Lowering the throttle with the switch still on must not arm
+    assert_eq!(vehicle.update(20_000, THROTTLE_DOWN, SWITCH_ON), ArmingStatus::Ready);
+    assert_eq!(vehicle.update(30_000, THROTTLE_DOWN, SWITCH_OFF), ArmingStatus::Ready);
+    assert_eq!(vehicle.update(40_000, THROTTLE_DOWN, SWITCH_ON), ArmingStatus::Armed);
+}
+
+#[test]
+fn reports_every_blocker() {
+
+    let mut vehicle = make_vehicle();
+
+    // No receiver frames yet
+    assert_eq!(vehicle.tick(0), ArmingStatus::Unready);
+    assert_eq!(
+        vehicle.arming.blockers(),
+        arming::BLOCKED_SWITCH_NOT_OFF | arming::BLOCKED_NO_SIGNAL);
+
+    vehicle.vstate.phi = -arming::MAX_ARMING_ANGLE_DEG;
+    vehicle.vstate.theta = 10.0;
+    vehicle.gyro_calibrating = true;
+
+    assert_eq!(vehicle.update(10_000, THROTTLE_UP, SWITCH_OFF), ArmingStatus::Unready);
+    assert_eq!(
+        vehicle.arming.blockers(),
+        arming::BLOCKED_THROTTLE_UP | arming::BLOCKED_ANGLE | arming::BLOCKED_GYRO_CALIBRATING);
+
+    vehicle.vstate.phi = 24.0;
+    vehicle.gyro_calibrating = false;
+
+    assert_eq!(vehicle.update(20_000, THROTTLE_DOWN, SWITCH_OFF), ArmingStatus::Ready);
+
+    // Signal lost: failsafe enters guard time once the receiver times out
+    assert_eq!(vehicle.tick(100_000), ArmingStatus::Unready);
+    assert_eq!(
+        vehicle.arming.blockers(),
+        arming::BLOCKED_NO_SIGNAL | arming::BLOCKED_FAILSAFE);
+}
+
+#[test]
+fn failsafe_landing_disarms_until_switch_cycled() {
+
+    let config = failsafe::DEFAULT_CONFIG;
+
+    let mut vehicle = make_vehicle();
+
+    vehicle.update(0, THROTTLE_DOWN, SWITCH_OFF);
+    vehicle.update(10_000, THROTTLE_DOWN, SWITCH_ON);
+    vehicle.update(20_000, THROTTLE_UP, SWITCH_ON);
+
+    // Stays armed through guard time and landing, after the signal times
+    // out 30 msec past the last frame
+    let landed_usec = 50_000 + config.guard_usec + config.landing_usec;
+
+    let mut usec = 30_000;
+
+    while usec < landed_usec {
+        assert_eq!(vehicle.tick(usec), ArmingStatus::Armed);
+        usec += 10_000;
+    }
+
+    assert_eq!(vehicle.tick(usec), ArmingStatus::Failsafe);
+
+    // Link back, with the pilot's switch still on
+    let link_usec = usec;
+
+    while usec < link_usec + config.recovery_usec + 100_000 {
+        assert_ne!(vehicle.update(usec, THROTTLE_DOWN, SWITCH_ON), ArmingStatus::Armed);
+        usec += 10_000;
+    }
+
+    assert_eq!(vehicle.arming.status(), ArmingStatus::Unready);
+    assert_eq!(vehicle.arming.blockers(), arming::BLOCKED_SWITCH_NOT_OFF);
+
+    assert_eq!(vehicle.update(usec, THROTTLE_DOWN, SWITCH_OFF), ArmingStatus::Ready);
+    assert_eq!(vehicle.update(usec + 10_000, THROTTLE_DOWN, SWITCH_ON), ArmingStatus::Armed);
+}
+
+#[test]
+fn step_zeroes_motors_when_disarmed() {
+
+    let vehicle = make_vehicle();
+
+    let demands = Demands { throttle: 0.5, roll: 0.1, pitch: 0.0, yaw: 0.0 };
+
+    let motors = step(&demands, &vehicle.vstate, &mut [], &false, &false, &0, &QUAD);
+
+    assert_eq!(motors.as_slice(), [0.0; 4]);
+
+    let motors = step(&demands, &vehicle.vstate, &mut [], &false, &true, &0, &QUAD);
+
+    assert!(motors.values[0] > 0.0);
+}
@@ -0,0 +1,210 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::arming;
+use hackflight::clock::LoopConfig;
+use hackflight::config;
+use hackflight::config::Error;
+use hackflight::config::FlightConfig;
+use hackflight::mixers::saturation::Priority;
+use hackflight::mixers::saturation::SaturationConfig;
+use hackflight::modes::FlightMode;
+use hackflight::modes::ModeRange;
+use hackflight::modes;
+use hackflight::output::OutputConfig;
+use hackflight::output::Protocol;
+use hackflight::failsafe;
+use hackflight::pids::AltHoldConfig;
+use hackflight::pids::AnglePidConfig;
+use hackflight::pids::InvalidConfig;
+use hackflight::rates::RateProfile;
+use hackflight::rates::Rates;
+
+// Differs from the default in every section
+fn tuned() -> FlightConfig {
+
+    let d = config::DEFAULT_CONFIG;
+
+    let mut modes = d.modes;
+
+    modes[1] = ModeRange { mode: FlightMode::Angle, aux: 1, start: 1300, end: 1700 };
+    modes[2] = ModeRange { mode: FlightMode::Horizon, aux: 1, start: 1700, end: 2100 };
+
+    FlightConfig {
+        angle_gains: config::AngleGains { k_level_p: 4.0, ..d.angle_gains },
+        alt_hold: AltHoldConfig { pilot_velz_max: 1.5, ..d.alt_hold },
+        rates: RateProfile {
+            yaw: Rates::Betaflight { rc_rate: 1.2, super_rate: 0.75, expo: 0.3 },
+            ..d.rates
+        },
+        modes,
+        failsafe: failsafe::Config { landing_usec: 5_000_000, ..d.failsafe },
+        output: OutputConfig {
+            thrust_linear: 0.3,
+            protocol: Protocol::Pwm { stop_usec: 1000, min_usec: 1070, max_usec: 2000 },
+            ..d.output
+        },
+        saturation: SaturationConfig { priority: Priority::Equal, airmode: true },
+        ..d
+    }
+}
+
+fn to_bytes(config: &FlightConfig) -> Vec<u8> {
+
+    let mut buf = [0; config::MAX_BYTES];
+
+    let len = config::to_bytes(config, &mut buf).unwrap();
+
+    buf[..len].to_vec()
+}
+
+#[test]
+fn binary_round_trip() {
+
+    for config in [config::DEFAULT_CONFIG, tuned()] {
+        assert_eq!(config::from_bytes(&to_bytes(&config)), Ok(config));
+    }
+}
+
+#[test]
+fn text_round_trip() {
+
+    let config = tuned();
+
+    let toml = config::to_toml(&config).unwrap();
+
+    assert!(toml.starts_with(&format!("version = {}\n", config::SCHEMA_VERSION)), "{}", toml);
+    assert_eq!(config::from_toml(&toml), Ok(config));
+
+    let json = config::to_json(&config).unwrap();
+
+    assert_eq!(config::from_json(&json), Ok(config));
+}
+
+#[test]
+fn newer_versions_are_refused() {
+
+    let next = config::SCHEMA_VERSION + 1;
+
+    // The version leads the blob, as a single-byte varint
+    let mut bytes = to_bytes(&config::DEFAULT_CONFIG);
+    bytes[0] = next as u8;
+
+    assert_eq!(config::from_bytes(&bytes), Err(Error::UnsupportedVersion(next)));
+
+    let json = config::to_json(&config::DEFAULT_CONFIG)
+        .unwrap()
+        .replace(&format!("\"version\": {}", config::SCHEMA_VERSION), &format!("\"version\": {}", next));
+
+    assert_eq!(config::from_json(&json), Err(Error::UnsupportedVersion(next)));
+}
+
+#[test]
+fn malformed_data_is_rejected() {
+
+    let bytes = to_bytes(&config::DEFAULT_CONFIG);
+
+    assert_eq!(config::from_bytes(&bytes[..bytes.len() / 2]), Err(Error::Encoding));
+    assert_eq!(config::from_bytes(&[]), Err(Error::Encoding));
+    assert_eq!(config::from_toml("version = 1\n"), Err(Error::Encoding));
+
+    let mut small = [0; 16];
+
+    assert_eq!(config::to_bytes(&config::DEFAULT_CONFIG, &mut small), Err(Error::Encoding));
+}
+
+#[test]
+fn invalid_config_is_rejected_at_load() {
+
+    let d = config::DEFAULT_CONFIG;
+
+    let load = |config: FlightConfig| config::from_bytes(&to_bytes(&config));
+
+    let invalid = |field| Err(Error::Invalid(InvalidConfig { field }));
+
+    let quick = Rates::Quick { rc_rate: 0.0, max_rate: 670.0, expo: 0.0 };
+
+    assert_eq!(load(FlightConfig { rates: RateProfile { pitch: quick, ..d.rates }, ..d }), invalid("rc_rate"));
+
+    assert_eq!(
+        load(FlightConfig { failsafe: failsafe::Config { descent_velocity: -1.0, ..d.failsafe }, ..d }),
+        invalid("descent_velocity"));
+
+    assert_eq!(load(FlightConfig { output: OutputConfig { idle: 0.5, ..d.output }, ..d }), invalid("idle"));
+
+    assert_eq!(
+        load(FlightConfig { angle_gains: config::AngleGains { k_level_p: 0.0, ..d.angle_gains }, ..d }),
+        invalid("k_level_p"));
+
+    assert_eq!(
+        load(FlightConfig { angle: AnglePidConfig { limit_yaw: 0.0, ..d.angle }, ..d }),
+        invalid("limit_yaw"));
+
+    assert_eq!(load(tuned()), Ok(tuned()));
+}
+
+#[test]
+fn default_modes_leave_the_arming_switch_alone() {
+
+    let on = arming::SWITCH_ON_MIN as u16;
+
+    for range in config::DEFAULT_CONFIG.modes.iter().filter(|range| range.start < range.end) {
+        assert!(range.aux != arming::SWITCH_AUX || range.end <= on, "{:?}", range);
+    }
+}
+
+#[test]
+fn largest_config_fits() {
+
+    let range = ModeRange { mode: FlightMode::Failsafe, aux: 1, start: u16::MAX - 1, end: u16::MAX };
+
+    let config = FlightConfig { modes: [range; modes::MAX_RANGES], ..tuned() };
+
+    let mut buf = [0; config::MAX_BYTES];
+
+    assert!(config::to_bytes(&config, &mut buf).is_ok());
+}
+
+#[test]
+fn loaded_config_makes_controllers_and_modes() {
+
+    let config = config::from_bytes(&to_bytes(&tuned())).unwrap();
+
+    assert!(config.make_angle(&LoopConfig { rate_hz: 1000 }).is_ok());
+    assert!(config.make_alt_hold().is_ok());
+    assert!(config.make_modes().is_ok());
+    assert!(config.make_failsafe().is_ok());
+    assert!(config.make_output().is_ok());
+
+    let mut bad = config;
+
+    bad.modes[3] = ModeRange { mode: FlightMode::Angle, aux: 7, start: 900, end: 2100 };
+
+    assert_eq!(bad.make_modes().err().unwrap().field, "modes");
+
+    // Failsafe couldn't level
+    bad.angle_gains.k_level_p = 0.0;
+
+    assert_eq!(bad.make_angle(&LoopConfig { rate_hz: 1000 }).err().unwrap().field, "k_level_p");
+
+    let mut bad = config;
+
+    bad.rates.roll = Rates::Quick { rc_rate: 0.0, max_rate: 670.0, expo: 0.0 };
+
+    assert_eq!(bad.make_angle(&LoopConfig { rate_hz: 1000 }).err().unwrap().field, "rc_rate");
+}
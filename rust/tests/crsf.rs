@@ -0,0 +1,150 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::VehicleState;
+use hackflight::receiver;
+use hackflight::receiver::crsf;
+use hackflight::receiver::crsf::Frame;
+
+// RC_CHANNELS_PACKED with all sixteen channels centered at 992
+const RC_CENTERED: [u8; 26] = [
+    0xC8, 0x18, 0x16, 0xE0, 0x03, 0x1F, 0xF8, 0xC0, 0x07, 0x3E, 0xF0, 0x81,
+    0x0F, 0x7C, 0xE0, 0x03, 0x1F, 0xF8, 0xC0, 0x07, 0x3E, 0xF0, 0x81, 0x0F,
+    0x7C, 0xAD
+];
+
+// LINK_STATISTICS: RSSI -58/-60 dBm, LQ 100%, SNR 9 dB, antenna 0, RF mode
+// 4, power 3; downlink RSSI -70 dBm, LQ 100%, SNR 8 dB
+const LINK_STATISTICS: [u8; 14] = [
+    0xC8, 0x0C, 0x14, 0x3A, 0x3C, 0x64, 0x09, 0x00, 0x04, 0x03, 0x46, 0x64,
+    0x08, 0x6B
+];
+
+fn parse_all(bytes: &[u8]) -> Vec<Frame> {
+
+    let mut decoder = crsf::make_decoder();
+
+    bytes.iter().filter_map(|&c| decoder.parse(c)).collect()
+}
+
+#[test]
+fn rc_channels_are_decoded() {
+
+    assert_eq!(
+        parse_all(&RC_CENTERED),
+        vec![Frame::RcChannels(crsf::RcChannels { channels: [992; crsf::NUM_CHANNELS] })]);
+}
+
+#[test]
+fn link_statistics_are_decoded() {
+
+    assert_eq!(
+        parse_all(&LINK_STATISTICS),
+        vec![Frame::LinkStatistics(crsf::LinkStatistics {
+            uplink_rssi_1: 58,
+            uplink_rssi_2: 60,
+            uplink_link_quality: 100,
+            uplink_snr: 9,
+            active_antenna: 0,
+            rf_mode: 4,
+            uplink_tx_power: 3,
+            downlink_rssi: 70,
+            downlink_link_quality: 100,
+            downlink_snr: 8
+        })]);
+}
+
+#[test]
+fn bad_crc_and_garbage_are_skipped() {
+
+    let mut corrupt = RC_CENTERED;
+    corrupt[10] ^= 0x01;
+
+    // Garbage, a bad length, a corrupt frame, a frame of a type we don't
+    // decode (flight mode), then good frames
+    let mut stream = vec![0x00, 0x55, 0xC8, 0xFF];
+    stream.extend(&corrupt);
+    stream.extend([0xC8, 0x08, 0x21, 0x41, 0x4E, 0x47, 0x4C, 0x45, 0x00, 0x87]);
+    stream.extend(&LINK_STATISTICS);
+    stream.extend(&RC_CENTERED);
+
+    let frames = parse_all(&stream);
+
+    assert_eq!(frames.len(), 2);
+    assert!(matches!(frames[0], Frame::LinkStatistics(_)));
+    assert!(matches!(frames[1], Frame::RcChannels(_)));
+}
+
+#[test]
+fn rc_channels_feed_the_receiver() {
+
+    let mut rx = receiver::make();
+
+    if let Frame::RcChannels(rc) = parse_all(&RC_CENTERED)[0] {
+        rc.set_receiver(&mut rx, 0);
+    }
+
+    assert!(rx.have_signal(0));
+
+    let demands = rx.get_demands();
+
+    assert!((demands.throttle - 0.473).abs() < 1e-6);
+    assert!(demands.pitch.abs() < 1e-3);
+}
+
+#[test]
+fn battery_telemetry_is_encoded() {
+
+    let mut encoder = crsf::make_encoder();
+
+    assert_eq!(
+        encoder.battery(12.6, 2.5, 1500, 80),
+        &[0xC8, 0x0A, 0x08, 0x00, 0x7E, 0x00, 0x19, 0x00, 0x05, 0xDC, 0x50, 0x5E]);
+}
+
+#[test]
+fn attitude_telemetry_is_encoded() {
+
+    let vstate = VehicleState {
+        x: 0.0, dx: 0.0, y: 0.0, dy: 0.0, z: 0.0, dz: 0.0,
+        phi: -20.0, dphi: 0.0, theta: 10.0, dtheta: 0.0, psi: 270.0, dpsi: 0.0
+    };
+
+    let mut encoder = crsf::make_encoder();
+
+    // Pitch 1745, roll -3491, yaw -15708
+    assert_eq!(
+        encoder.attitude(&vstate),
+        &[0xC8, 0x08, 0x1E, 0x06, 0xD1, 0xF2, 0x5D, 0xC2, 0xA4, 0x18]);
+}
+
+#[test]
+fn flight_mode_telemetry_is_encoded() {
+
+    let mut encoder = crsf::make_encoder();
+
+    assert_eq!(
+        encoder.flight_mode("ANGLE"),
+        &[0xC8, 0x08, 0x21, 0x41, 0x4E, 0x47, 0x4C, 0x45, 0x00, 0x87]);
+
+    // Long names are truncated
+    let frame = encoder.flight_mode("A VERY LONG FLIGHT MODE NAME").to_vec();
+
+    assert_eq!(frame.len(), 3 + crsf::FLIGHT_MODE_MAX_LENGTH + 2);
+    assert_eq!(frame[frame.len() - 2], 0);
+}
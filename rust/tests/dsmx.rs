@@ -0,0 +1,162 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::receiver;
+use hackflight::receiver::dsmx;
+use hackflight::receiver::dsmx::Resolution;
+
+// Time between bytes at 115200 baud
+const BYTE_USEC: u32 = 87;
+
+// Builds a frame from (channel ID, raw value) pairs, padding with unused
+// words
+fn frame(resolution: Resolution, fades: u8, system: u8, words: &[(u16, u16)]) -> Vec<u8> {
+
+    let shift = match resolution {
+        Resolution::Bits10 => 10,
+        Resolution::Bits11 => 11
+    };
+
+    let mut bytes = vec![fades, system];
+
+    for k in 0..7 {
+        let word = match words.get(k) {
+            Some(&(id, value)) => (id << shift) | value,
+            None => 0xFFFF
+        };
+        bytes.extend(word.to_be_bytes());
+    }
+
+    bytes
+}
+
+// Feeds a frame starting at the given time, returning whether it completed
+fn feed(decoder: &mut dsmx::Decoder, bytes: &[u8], start_usec: u32) -> bool {
+
+    let mut done = false;
+
+    for (k, &c) in bytes.iter().enumerate() {
+        done = decoder.parse(c, start_usec + (k as u32) * BYTE_USEC);
+    }
+
+    done
+}
+
+#[test]
+fn dsm2_1024_frame_is_decoded() {
+
+    let mut decoder = dsmx::make_decoder(Resolution::Bits10);
+
+    let bytes = frame(Resolution::Bits10, 0, dsmx::SYSTEM_DSM2_1024_22MS,
+        &[(0, 0), (1, 512), (2, 1023), (3, 100), (4, 0), (5, 1023)]);
+
+    assert!(feed(&mut decoder, &bytes, 0));
+
+    assert_eq!(decoder.channels()[..6], [988, 1500, 2011, 1088, 988, 2011]);
+    assert_eq!(decoder.system(), dsmx::SYSTEM_DSM2_1024_22MS);
+}
+
+#[test]
+fn dsmx_channels_split_across_frames_are_merged() {
+
+    let mut decoder = dsmx::make_decoder(Resolution::Bits11);
+
+    let first = frame(Resolution::Bits11, 0, dsmx::SYSTEM_DSMX_2048_11MS,
+        &[(0, 24), (1, 1024), (2, 2046), (3, 1024), (4, 0), (5, 2046), (6, 1024)]);
+
+    let second = frame(Resolution::Bits11, 0, dsmx::SYSTEM_DSMX_2048_11MS,
+        &[(7, 1024), (8, 0), (9, 2046), (10, 1024), (11, 1024)]);
+
+    assert!(feed(&mut decoder, &first, 0));
+    assert!(feed(&mut decoder, &second, 11000));
+
+    assert_eq!(
+        decoder.channels(),
+        [1000, 1500, 2011, 1500, 988, 2011, 1500, 1500, 988, 2011, 1500, 1500]);
+}
+
+#[test]
+fn decoder_resyncs_on_frame_gap() {
+
+    let mut decoder = dsmx::make_decoder(Resolution::Bits11);
+
+    let bytes = frame(Resolution::Bits11, 0, dsmx::SYSTEM_DSMX_2048_22MS, &[(0, 1024)]);
+
+    // Joining in the middle of a frame, then seeing a whole one
+    assert!(!feed(&mut decoder, &bytes[7..], 0));
+    assert!(feed(&mut decoder, &bytes, 11000));
+
+    assert_eq!(decoder.channels()[0], 1500);
+
+    // A truncated frame is dropped at the next gap
+    let other = frame(Resolution::Bits11, 0, dsmx::SYSTEM_DSMX_2048_22MS, &[(0, 0)]);
+
+    assert!(!feed(&mut decoder, &other[..10], 22000));
+    assert!(feed(&mut decoder, &bytes, 33000));
+
+    assert_eq!(decoder.channels()[0], 1500);
+}
+
+#[test]
+fn fades_are_counted_across_wraparound() {
+
+    let mut decoder = dsmx::make_decoder(Resolution::Bits11);
+
+    let mut usec = 0;
+
+    for fades in [250, 250, 253, 255, 2] {
+
+        let bytes = frame(Resolution::Bits11, fades, dsmx::SYSTEM_DSM2_2048_11MS, &[]);
+
+        feed(&mut decoder, &bytes, usec);
+
+        usec += 11000;
+    }
+
+    assert_eq!(decoder.fades(), 8);
+}
+
+#[test]
+fn frames_feed_the_receiver_until_timeout() {
+
+    let mut decoder = dsmx::make_decoder(Resolution::Bits11);
+
+    let mut rx = receiver::make();
+
+    assert!(decoder.timed_out(0));
+
+    let bytes = frame(Resolution::Bits11, 0, dsmx::SYSTEM_DSMX_2048_11MS,
+        &[(0, 0), (1, 1024), (2, 1024), (3, 2046)]);
+
+    feed(&mut decoder, &bytes, 0);
+
+    let usec = 15 * BYTE_USEC;
+
+    decoder.set_receiver(&mut rx, usec);
+
+    assert!(rx.have_signal(usec));
+    assert!(rx.throttle_is_down());
+
+    let demands = rx.get_demands();
+
+    assert!(demands.roll.abs() < 0.01);
+    assert!((demands.yaw + 1.0).abs() < 0.01);
+
+    assert!(!decoder.timed_out(usec + 50000));
+    assert!(decoder.timed_out(usec + 50001));
+}
@@ -0,0 +1,141 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::f32::consts::PI;
+
+use hackflight::VehicleState;
+use hackflight::clock::LoopConfig;
+use hackflight::filters::dyn_notch;
+
+const RATE_HZ: u32 = 8_000;
+
+fn make_state(dphi: f32, dtheta: f32, dpsi: f32) -> VehicleState {
+
+    VehicleState {
+        x: 0.0, dx: 0.0, y: 0.0, dy: 0.0, z: 0.0, dz: 0.0,
+        phi: 0.0, dphi, theta: 0.0, dtheta, psi: 0.0, dpsi
+    }
+}
+
+// Slow rotation on roll, one motor-noise line on pitch, two on yaw
+fn gyro(k: usize) -> VehicleState {
+
+    let t = (k as f32) / (RATE_HZ as f32);
+
+    let tone = |freq: f32, amplitude: f32| amplitude * (2.0 * PI * freq * t).sin();
+
+    make_state(
+        tone(2.0, 50.0),
+        tone(2.0, 50.0) + tone(250.0, 10.0),
+        tone(180.0, 10.0) + tone(420.0, 8.0))
+}
+
+fn run(dn: &mut dyn_notch::DynNotch, seconds: f32) -> Vec<VehicleState> {
+
+    (0..(seconds * RATE_HZ as f32) as usize).map(|k| dn.apply(&gyro(k))).collect()
+}
+
+#[test]
+fn finds_noise_peaks_on_each_axis() {
+
+    let mut dn = dyn_notch::make(&LoopConfig { rate_hz: RATE_HZ }, 100.0, 600.0, 3, 3.0).unwrap();
+
+    run(&mut dn, 2.0);
+
+    // No noise on roll
+    assert_eq!(dn.peaks(0), [None, None, None]);
+
+    let pitch = dn.peaks(1);
+    assert!((pitch[0].unwrap() - 250.0).abs() < 10.0, "{:?}", pitch);
+    assert_eq!(pitch[1], None);
+
+    let yaw = dn.peaks(2);
+    assert!((yaw[0].unwrap() - 180.0).abs() < 10.0, "{:?}", yaw);
+    assert!((yaw[1].unwrap() - 420.0).abs() < 10.0, "{:?}", yaw);
+}
+
+#[test]
+fn attenuates_noise_and_passes_motion() {
+
+    let mut dn = dyn_notch::make(&LoopConfig { rate_hz: RATE_HZ }, 100.0, 600.0, 3, 3.0).unwrap();
+
+    let out = run(&mut dn, 3.0);
+
+    // Compare over the final second, once the notches have settled
+    let settled = &out[out.len() - RATE_HZ as usize..];
+    let offset = out.len() - RATE_HZ as usize;
+
+    let rms = |f: &dyn Fn(usize) -> f32| {
+        ((0..settled.len()).map(|k| f(k) * f(k)).sum::<f32>() / settled.len() as f32).sqrt()
+    };
+
+    let roll_error = rms(&|k| settled[k].dphi - gyro(offset + k).dphi);
+
+    let pitch_noise_in = rms(&|k| gyro(offset + k).dtheta - gyro(offset + k).dphi);
+    let pitch_noise_out = rms(&|k| settled[k].dtheta - gyro(offset + k).dphi);
+
+    assert!(roll_error < 1e-3, "roll error {}", roll_error);
+    assert!(pitch_noise_out < 0.2 * pitch_noise_in,
+        "pitch noise {} => {}", pitch_noise_in, pitch_noise_out);
+}
+
+#[test]
+fn notch_count_limits_reported_peaks() {
+
+    let mut dn = dyn_notch::make(&LoopConfig { rate_hz: RATE_HZ }, 100.0, 600.0, 1, 3.0).unwrap();
+
+    run(&mut dn, 2.0);
+
+    let yaw = dn.peaks(2);
+
+    // Only the stronger line is tracked
+    assert!((yaw[0].unwrap() - 180.0).abs() < 10.0, "{:?}", yaw);
+    assert_eq!(yaw[1], None);
+}
+
+#[test]
+fn bad_bands_are_rejected() {
+
+    let field = |rate_hz, min_hz, max_hz| {
+        dyn_notch::make(&LoopConfig { rate_hz }, min_hz, max_hz, 3, 3.0).err().unwrap().field
+    };
+
+    // Above the Nyquist frequency
+    assert_eq!(field(1000, 500.0, 600.0), "max_hz");
+
+    assert_eq!(field(RATE_HZ, 600.0, 100.0), "max_hz");
+    assert_eq!(field(RATE_HZ, 0.0, 600.0), "min_hz");
+    assert_eq!(field(0, 100.0, 600.0), "rate_hz");
+
+    // Only in the top bins, which aren't searched
+    assert_eq!(field(1000, 490.0, 500.0), "min_hz");
+
+    let notches = |count| {
+        dyn_notch::make(&LoopConfig { rate_hz: RATE_HZ }, 100.0, 600.0, count, 3.0).err().unwrap().field
+    };
+
+    assert_eq!(notches(0), "notch_count");
+    assert_eq!(notches(dyn_notch::MAX_PEAKS + 1), "notch_count");
+
+    // Right up to the Nyquist frequency is fine
+    let mut edge = dyn_notch::make(&LoopConfig { rate_hz: 1000 }, 100.0, 500.0, 3, 3.0).unwrap();
+
+    for k in 0..1000 {
+        edge.apply(&gyro(k));
+    }
+}
@@ -0,0 +1,172 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::VehicleState;
+use hackflight::ekf;
+
+const DT: f32 = 0.001;
+
+// Optical flow sensor resolution (pixels) and field of view (radians)
+const FLOW_NPIX: f32 = 35.0;
+const FLOW_THETAPIX: f32 = 0.71674;
+
+fn level() -> VehicleState {
+
+    VehicleState {
+        x: 0.0, dx: 0.0, y: 0.0, dy: 0.0, z: 0.0, dz: 0.0,
+        phi: 0.0, dphi: 0.0, theta: 0.0, dtheta: 0.0, psi: 0.0, dpsi: 0.0
+    }
+}
+
+fn estimate(ekf: &ekf::Ekf) -> VehicleState {
+
+    let mut vstate = level();
+
+    ekf.set_vehicle_state(&mut vstate);
+
+    vstate
+}
+
+#[test]
+fn baro_altitude_converges() {
+
+    let mut ekf = ekf::make();
+
+    let vstate = level();
+
+    for k in 0..5000 {
+
+        ekf.predict(DT, [0.0, 0.0, 1.0], &vstate);
+
+        // Baro at 50 Hz
+        if k % 20 == 0 {
+            ekf.update_baro(2.0);
+        }
+    }
+
+    let est = estimate(&ekf);
+
+    assert!((est.z - 2.0).abs() < 0.1, "z = {}", est.z);
+    assert!(est.dz.abs() < 0.1, "dz = {}", est.dz);
+}
+
+#[test]
+fn climb_is_tracked_with_accelerometer_and_rangefinder() {
+
+    let mut ekf = ekf::make();
+
+    let vstate = level();
+
+    let (mut z, mut dz) = (0.2, 0.0);
+
+    for k in 0..3000 {
+
+        // Accelerate upward at 1 m/s^2 for one second, then coast
+        let az = if k < 1000 { 1.0 } else { 0.0 };
+
+        dz += az * DT;
+        z += dz * DT;
+
+        ekf.predict(DT, [0.0, 0.0, 1.0 + az / 9.81], &vstate);
+
+        if k % 20 == 0 {
+            ekf.update_range(z, &vstate);
+        }
+    }
+
+    let est = estimate(&ekf);
+
+    assert!((est.z - z).abs() < 0.05, "z = {} (true {})", est.z, z);
+    assert!((est.dz - dz).abs() < 0.05, "dz = {} (true {})", est.dz, dz);
+}
+
+#[test]
+fn tilted_rangefinder_is_corrected_for_attitude() {
+
+    let mut ekf = ekf::make();
+
+    let mut vstate = level();
+
+    vstate.phi = 30.0;
+
+    let tilt = 30.0f32.to_radians().cos();
+
+    for k in 0..2000 {
+
+        // Thrust along the tilted body axis balances gravity vertically
+        ekf.predict(DT, [0.0, 0.0, 1.0 / tilt], &vstate);
+
+        if k % 20 == 0 {
+            ekf.update_range(1.0 / tilt, &vstate);
+        }
+    }
+
+    let est = estimate(&ekf);
+
+    assert!((est.z - 1.0).abs() < 0.05, "z = {}", est.z);
+}
+
+#[test]
+fn optical_flow_gives_horizontal_velocity() {
+
+    let mut ekf = ekf::make();
+
+    let vstate = level();
+
+    let (z, dx, dy) = (1.0, 0.5, -0.3);
+
+    let flow_period = 0.01;
+
+    for k in 0..3000 {
+
+        ekf.predict(DT, [0.0, 0.0, 1.0], &vstate);
+
+        if k % 10 == 0 {
+
+            ekf.update_range(z, &vstate);
+
+            let c = flow_period * FLOW_NPIX / FLOW_THETAPIX;
+
+            ekf.update_flow(c * dx / z, c * dy / z, flow_period, &vstate);
+        }
+    }
+
+    let est = estimate(&ekf);
+
+    assert!((est.dx - dx).abs() < 0.05, "dx = {}", est.dx);
+    assert!((est.dy - dy).abs() < 0.05, "dy = {}", est.dy);
+
+    // Position is integrated from the estimated velocity
+    assert!((est.x - 3.0 * dx).abs() < 0.3, "x = {}", est.x);
+    assert!((est.z - z).abs() < 0.05, "z = {}", est.z);
+}
+
+#[test]
+fn vl53l5_range_uses_valid_center_zones() {
+
+    let mut zones = [0i16; 16];
+
+    zones[5] = 1000;
+    zones[6] = 1200;
+    zones[9] = -1;
+    zones[10] = 1100;
+
+    assert!((ekf::vl53l5_range(&zones) - 1.1).abs() < 1e-6);
+
+    assert_eq!(ekf::vl53l5_range(&[0; 16]), 0.0);
+}
@@ -0,0 +1,218 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::Demands;
+use hackflight::failsafe;
+use hackflight::failsafe::Config;
+use hackflight::failsafe::Failsafe;
+use hackflight::failsafe::Phase;
+use hackflight::pids;
+use hackflight::pids::AltHoldConfig;
+use hackflight::receiver;
+use hackflight::receiver::Receiver;
+
+// Receiver frames arrive every 10 msec; the loop runs every 1 msec
+const FRAME_USEC: u32 = 10_000;
+const LOOP_USEC: u32 = 1_000;
+
+// Throttle up, rolled right
+const CHANNELS: [u16; receiver::CHANNEL_COUNT] = [1600, 1750, 1500, 1500, 1000, 1000];
+
+struct Sim {
+
+    receiver: Receiver,
+    failsafe: Failsafe,
+    usec: u32
+}
+
+impl Sim {
+
+    // Runs the loop until the given time, with frames arriving whenever the
+    // link is up
+    fn run(&mut self, until_usec: u32, link_up: bool) -> Phase {
+
+        while self.usec < until_usec {
+
+            if link_up && self.usec.is_multiple_of(FRAME_USEC) {
+                self.receiver.set_values(&CHANNELS, self.usec, false, 1000, 2000);
+            }
+
+            self.failsafe.update(self.usec, self.receiver.have_signal(self.usec));
+
+            self.usec += LOOP_USEC;
+        }
+
+        self.failsafe.phase()
+    }
+
+    fn demands(&mut self, altitude_valid: bool) -> Demands {
+
+        self.demands_with(&pids::DEFAULT_ALT_HOLD_CONFIG, altitude_valid)
+    }
+
+    fn demands_with(&mut self, alt_hold: &AltHoldConfig, altitude_valid: bool) -> Demands {
+
+        let sticks = self.receiver.get_demands();
+
+        self.failsafe.get_demands(&sticks, alt_hold, altitude_valid)
+    }
+}
+
+fn make_sim() -> Sim {
+
+    Sim {
+        receiver: receiver::make(),
+        failsafe: failsafe::make(failsafe::DEFAULT_CONFIG).unwrap(),
+        usec: 0
+    }
+}
+
+#[test]
+fn stays_idle_until_signal_first_seen() {
+
+    let mut sim = make_sim();
+
+    assert_eq!(sim.run(5_000_000, false), Phase::Idle);
+    assert!(!sim.failsafe.should_disarm());
+}
+
+#[test]
+fn brief_dropout_holds_sticks_and_recovers() {
+
+    let mut sim = make_sim();
+
+    assert_eq!(sim.run(1_000_000, true), Phase::Idle);
+
+    // Signal is only declared lost 30 msec after the last frame
+    assert_eq!(sim.run(1_015_000, false), Phase::Idle);
+    assert_eq!(sim.run(1_500_000, false), Phase::GuardTime);
+
+    let demands = sim.demands(false);
+    assert!(demands.throttle > 0.0);
+    assert!(demands.roll > 0.0);
+
+    // Guard time ends as soon as the signal is back
+    assert_eq!(sim.run(1_600_000, true), Phase::Idle);
+}
+
+#[test]
+fn long_dropout_lands_then_disarms() {
+
+    let config = failsafe::DEFAULT_CONFIG;
+
+    let mut sim = make_sim();
+
+    sim.run(1_000_000, true);
+
+    let lost_usec = 1_030_000;
+
+    assert_eq!(sim.run(lost_usec + config.guard_usec + 2 * LOOP_USEC, false), Phase::Landing);
+
+    let demands = sim.demands(false);
+    assert_eq!(demands.throttle, config.descent_throttle);
+    assert_eq!([demands.roll, demands.pitch, demands.yaw], [0.0; 3]);
+    assert!(!sim.failsafe.should_disarm());
+
+    let landed_usec = lost_usec + config.guard_usec + config.landing_usec;
+
+    assert_eq!(sim.run(landed_usec + 2 * LOOP_USEC, false), Phase::Landed);
+    assert!(sim.failsafe.should_disarm());
+    assert_eq!(sim.demands(false).throttle, 0.0);
+}
+
+#[test]
+fn landing_uses_alt_hold_descent_when_altitude_valid() {
+
+    let mut sim = make_sim();
+
+    sim.run(1_000_000, true);
+    sim.run(2_500_000, false);
+
+    assert_eq!(sim.failsafe.phase(), Phase::Landing);
+
+    let throttle = sim.demands(true).throttle;
+
+    // Below mid-stick, and outside the alt-hold deadband, means descend
+    assert!(throttle < 0.5);
+    assert_eq!(throttle, pids::alt_hold_throttle(&pids::DEFAULT_ALT_HOLD_CONFIG, -1.0));
+
+    // The descent follows the alt-hold tuning flown with
+    let tuned = AltHoldConfig { pilot_velz_max: 1.5, ..pids::DEFAULT_ALT_HOLD_CONFIG };
+
+    let tuned_throttle = sim.demands_with(&tuned, true).throttle;
+
+    assert_eq!(tuned_throttle, pids::alt_hold_throttle(&tuned, -1.0));
+    assert_ne!(tuned_throttle, throttle);
+}
+
+#[test]
+fn recovery_requires_steady_signal() {
+
+    let config = failsafe::DEFAULT_CONFIG;
+
+    let mut sim = make_sim();
+
+    sim.run(1_000_000, true);
+    sim.run(3_000_000, false);
+
+    assert_eq!(sim.failsafe.phase(), Phase::Landing);
+
+    // Signal back, but not yet for long enough
+    assert_eq!(sim.run(3_000_000 + config.recovery_usec / 2, true), Phase::Landing);
+
+    // A glitch restarts the recovery period
+    let glitch_end = sim.usec + 50_000;
+    assert_eq!(sim.run(glitch_end, false), Phase::Landing);
+    assert_eq!(sim.run(glitch_end + config.recovery_usec / 2, true), Phase::Landing);
+
+    assert_eq!(sim.run(glitch_end + config.recovery_usec + 2 * LOOP_USEC, true), Phase::Idle);
+}
+
+#[test]
+fn landed_recovers_to_idle_after_steady_signal() {
+
+    let config = failsafe::DEFAULT_CONFIG;
+
+    let mut sim = make_sim();
+
+    sim.run(1_000_000, true);
+    sim.run(20_000_000, false);
+
+    assert!(sim.failsafe.should_disarm());
+
+    sim.run(20_000_000 + config.recovery_usec + 2 * LOOP_USEC, true);
+
+    assert_eq!(sim.failsafe.phase(), Phase::Idle);
+    assert!(!sim.failsafe.should_disarm());
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+
+    let d = failsafe::DEFAULT_CONFIG;
+
+    let field = |config| failsafe::make(config).err().unwrap().field;
+
+    // Would climb while landing
+    assert_eq!(field(Config { descent_velocity: -1.0, ..d }), "descent_velocity");
+    assert_eq!(field(Config { descent_velocity: 0.0, ..d }), "descent_velocity");
+
+    assert_eq!(field(Config { descent_throttle: 1.5, ..d }), "descent_throttle");
+    assert_eq!(field(Config { descent_throttle: f32::NAN, ..d }), "descent_throttle");
+    assert_eq!(field(Config { landing_usec: 0, ..d }), "landing_usec");
+}
@@ -0,0 +1,239 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::Mutex;
+
+use hackflight::Demands;
+use hackflight::Mixer;
+use hackflight::Motors;
+use hackflight::VehicleState;
+use hackflight::clock::LoopConfig;
+use hackflight::ffi;
+use hackflight::mixers::MixerType;
+use hackflight::mixers::quadxbf::QuadXbf;
+use hackflight::mixers::saturation;
+use hackflight::mixers::saturation::Priority;
+use hackflight::mixers::saturation::SaturationConfig;
+use hackflight::output;
+use hackflight::output::OutputConfig;
+use hackflight::output::Protocol;
+use hackflight::pids;
+
+// The controller pool is global, so tests must not run concurrently
+static LOCK: Mutex<()> = Mutex::new(());
+
+const RATE_HZ: u32 = 1_000;
+
+fn demands() -> Demands {
+
+    Demands { throttle: 0.6, roll: 0.1, pitch: -0.2, yaw: 0.05 }
+}
+
+fn vstate(k: u32) -> VehicleState {
+
+    let t = k as f32 / RATE_HZ as f32;
+
+    VehicleState {
+        x: 0.0, dx: 0.0, y: 0.0, dy: 0.0, z: 1.0, dz: 0.1 * t,
+        phi: 2.0 * t, dphi: 20.0, theta: -t, dtheta: -10.0, psi: 0.0, dpsi: 5.0
+    }
+}
+
+fn zero_motors() -> Motors {
+
+    hackflight::make_motors_off(0)
+}
+
+#[test]
+fn step_matches_rust_core() {
+
+    let _lock = LOCK.lock().unwrap();
+
+    let loop_config = LoopConfig { rate_hz: RATE_HZ };
+
+    let mut pids = [
+        pids::make_angle(
+            0.0125, 0.0103, 0.0000625, 0.0001756, 3.0, &loop_config, &pids::DEFAULT_ANGLE_CONFIG)
+            .unwrap(),
+        pids::make_alt_hold(0.75, 1.5, &pids::DEFAULT_ALT_HOLD_CONFIG).unwrap()
+    ];
+
+    unsafe { ffi::hf_reset(); }
+    assert!(unsafe { ffi::hf_add_angle_pid(0.0125, 0.0103, 0.0000625, 0.0001756, 3.0, RATE_HZ) });
+    assert!(unsafe { ffi::hf_add_alt_hold_pid(0.75, 1.5) });
+
+    let mixer = QuadXbf { saturation: saturation::DEFAULT_CONFIG };
+
+    for k in 0..500 {
+
+        let usec = k * 1_000_000 / RATE_HZ;
+
+        let expected = hackflight::step(&demands(), &vstate(k), &mut pids, &false, &true, &usec, &mixer);
+
+        let mut motors = zero_motors();
+
+        unsafe { ffi::hf_step(&demands(), &vstate(k), false, true, usec, &mut motors); }
+
+        assert_eq!(motors, expected);
+    }
+}
+
+#[test]
+fn pool_is_bounded_and_reset_clears_it() {
+
+    let _lock = LOCK.lock().unwrap();
+
+    unsafe { ffi::hf_reset(); }
+
+    for _ in 0..ffi::MAX_CONTROLLERS {
+        assert!(unsafe { ffi::hf_add_alt_hold_pid(0.75, 1.5) });
+    }
+
+    assert!(!unsafe { ffi::hf_add_alt_hold_pid(0.75, 1.5) });
+
+    unsafe { ffi::hf_reset(); }
+
+    // With no controllers, the stick demands go straight to the mixer
+    let mut motors = zero_motors();
+
+    unsafe { ffi::hf_step(&demands(), &vstate(0), false, true, 0, &mut motors); }
+
+    let expected = QuadXbf { saturation: saturation::DEFAULT_CONFIG }.get_motors(&demands());
+
+    assert_eq!(motors, expected);
+
+    // Disarmed
+    unsafe { ffi::hf_step(&demands(), &vstate(0), false, false, 0, &mut motors); }
+
+    assert_eq!(motors.as_slice(), [0.0; 4]);
+}
+
+#[test]
+fn mixer_can_be_chosen() {
+
+    let _lock = LOCK.lock().unwrap();
+
+    unsafe { ffi::hf_reset(); }
+    unsafe { ffi::hf_set_mixer(MixerType::HexX); }
+
+    let mut motors = zero_motors();
+
+    unsafe { ffi::hf_step(&demands(), &vstate(0), false, true, 0, &mut motors); }
+
+    assert_eq!(motors, MixerType::HexX.mixer(&saturation::DEFAULT_CONFIG).get_motors(&demands()));
+
+    // Reset goes back to the quad
+    unsafe { ffi::hf_reset(); }
+    unsafe { ffi::hf_step(&demands(), &vstate(0), false, true, 0, &mut motors); }
+
+    assert_eq!(motors.count, 4);
+}
+
+#[test]
+fn header_is_up_to_date() {
+
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/hackflight.h"));
+
+    assert!(include_str!("../include/hackflight.h") == generated,
+        "include/hackflight.h is stale; rebuild with HACKFLIGHT_UPDATE_HEADER=1");
+}
+
+#[test]
+fn header_motor_count_matches() {
+
+    let header = include_str!("../include/hackflight.h");
+
+    assert!(header.contains(&format!("#define HfMAX_MOTORS {}\n", hackflight::MAX_MOTORS)));
+}
+
+#[test]
+fn saturation_can_be_chosen() {
+
+    let _lock = LOCK.lock().unwrap();
+
+    let config = SaturationConfig { priority: Priority::Equal, airmode: true };
+
+    // Enough roll and yaw to saturate the motors
+    let demands = Demands { throttle: 0.9, roll: 0.3, pitch: 0.0, yaw: 0.3 };
+
+    let mut motors = zero_motors();
+
+    unsafe { ffi::hf_reset(); }
+    unsafe { ffi::hf_set_saturation(config); }
+    unsafe { ffi::hf_step(&demands, &vstate(0), false, true, 0, &mut motors); }
+
+    assert_eq!(motors, MixerType::QuadXbf.mixer(&config).get_motors(&demands));
+
+    // Reset goes back to the default
+    unsafe { ffi::hf_reset(); }
+    unsafe { ffi::hf_step(&demands, &vstate(0), false, true, 0, &mut motors); }
+
+    assert_eq!(motors, MixerType::QuadXbf.mixer(&saturation::DEFAULT_CONFIG).get_motors(&demands));
+}
+
+#[test]
+fn output_can_be_chosen() {
+
+    let _lock = LOCK.lock().unwrap();
+
+    let pwm = OutputConfig {
+        sag_compensation: 1.0,
+        protocol: Protocol::Pwm { stop_usec: 1000, min_usec: 1070, max_usec: 2000 },
+        ..output::DEFAULT_CONFIG
+    };
+
+    let motors = QuadXbf { saturation: saturation::DEFAULT_CONFIG }.get_motors(&demands());
+
+    let expected = |config, armed, voltage| {
+        output::make(config).unwrap().get_commands(&motors, armed, Some(voltage))
+    };
+
+    let mut commands = output::DEFAULT_OUTPUT.get_commands(&motors, false, None);
+
+    unsafe { ffi::hf_reset(); }
+    unsafe { ffi::hf_output(&motors, true, 0.0, &mut commands); }
+
+    assert_eq!(commands, expected(output::DEFAULT_CONFIG, true, 0.0));
+
+    assert!(unsafe { ffi::hf_set_output(pwm) });
+
+    unsafe { ffi::hf_output(&motors, true, 15.2, &mut commands); }
+
+    assert_eq!(commands, expected(pwm, true, 15.2));
+
+    unsafe { ffi::hf_output(&motors, false, 15.2, &mut commands); }
+
+    assert_eq!(commands.as_slice(), [1000; 4]);
+
+    // An invalid config keeps the previous one
+    assert!(!unsafe { ffi::hf_set_output(OutputConfig { idle: 0.5, ..pwm }) });
+
+    unsafe { ffi::hf_output(&motors, true, 15.2, &mut commands); }
+
+    assert_eq!(commands, expected(pwm, true, 15.2));
+}
+
+#[test]
+fn angle_pid_needs_a_level_gain() {
+
+    let _lock = LOCK.lock().unwrap();
+
+    unsafe { ffi::hf_reset(); }
+
+    assert!(!unsafe { ffi::hf_add_angle_pid(0.0125, 0.0103, 0.0000625, 0.0001756, 0.0, RATE_HZ) });
+}
@@ -0,0 +1,268 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::f32::consts::PI;
+
+use hackflight::clock::LoopConfig;
+use hackflight::filters;
+
+const RATES_HZ: [u32; 3] = [1_000, 8_000, 32_000];
+
+// Number of samples in one RC time constant for a first-order cutoff
+fn time_constant_samples(f_cut: f32, dt: f32) -> usize {
+
+    (1.0 / (2.0 * PI * f_cut * dt)).round() as usize
+}
+
+// Steady-state gain of a filter driven by a sinusoid at the given frequency
+fn sine_gain(apply: &mut dyn FnMut(f32) -> f32, freq: f32, dt: f32) -> f32 {
+
+    let period = (1.0 / (freq * dt)).round() as usize;
+
+    let mut peak: f32 = 0.0;
+
+    for k in 0..40 * period {
+
+        let y = apply((2.0 * PI * freq * (k as f32) * dt).sin());
+
+        // Skip the transient
+        if k >= 30 * period {
+            peak = peak.max(y.abs());
+        }
+    }
+
+    peak
+}
+
+#[test]
+fn pt1_step_reaches_63_percent_after_one_time_constant() {
+
+    for rate_hz in RATES_HZ {
+
+        let dt = LoopConfig { rate_hz }.dt();
+
+        let f_cut = 5.0;
+
+        let mut filter = filters::make_pt1(f_cut, dt);
+
+        let mut y = 0.0;
+
+        for _ in 0..time_constant_samples(f_cut, dt) {
+            y = filter.apply(1.0);
+        }
+
+        assert!((y - (1.0 - (-1.0f32).exp())).abs() < 0.01, "{} Hz: y = {}", rate_hz, y);
+    }
+}
+
+#[test]
+fn pt1_step_converges() {
+
+    let dt = LoopConfig { rate_hz: 1_000 }.dt();
+
+    let f_cut = 5.0;
+
+    let mut filter = filters::make_pt1(f_cut, dt);
+
+    let mut y = 0.0;
+
+    for _ in 0..10 * time_constant_samples(f_cut, dt) {
+        y = filter.apply(1.0);
+    }
+
+    assert!((y - 1.0).abs() < 1e-3, "y = {}", y);
+}
+
+#[test]
+fn pt1_set_cutoff_changes_time_constant() {
+
+    let dt = LoopConfig { rate_hz: 8_000 }.dt();
+
+    let mut slow = filters::make_pt1(20.0, dt);
+    let mut fast = filters::make_pt1(20.0, dt);
+
+    fast.set_cutoff(80.0);
+
+    let n = time_constant_samples(80.0, dt);
+
+    let mut y_slow = 0.0;
+    let mut y_fast = 0.0;
+
+    for _ in 0..n {
+        y_slow = slow.apply(1.0);
+        y_fast = fast.apply(1.0);
+    }
+
+    assert!((y_fast - (1.0 - (-1.0f32).exp())).abs() < 0.01, "y_fast = {}", y_fast);
+    assert!(y_slow < y_fast);
+}
+
+#[test]
+fn cutoff_attenuation_is_independent_of_loop_rate() {
+
+    let half_power = 1.0 / 2.0f32.sqrt();
+
+    let f_cut = 5.0;
+
+    for rate_hz in RATES_HZ {
+
+        let dt = LoopConfig { rate_hz }.dt();
+
+        let mut pt1 = filters::make_pt1(f_cut, dt);
+        let mut pt2 = filters::make_pt2(f_cut, dt);
+        let mut pt3 = filters::make_pt3(f_cut, dt);
+
+        let g1 = sine_gain(&mut |x| pt1.apply(x), f_cut, dt);
+        let g2 = sine_gain(&mut |x| pt2.apply(x), f_cut, dt);
+        let g3 = sine_gain(&mut |x| pt3.apply(x), f_cut, dt);
+
+        for g in [g1, g2, g3] {
+            assert!((g - half_power).abs() < 0.02, "{} Hz: gain = {}", rate_hz, g);
+        }
+    }
+}
+
+#[test]
+fn pt2_and_pt3_step_responses_are_monotonic_and_converge() {
+
+    let dt = LoopConfig { rate_hz: 1_000 }.dt();
+
+    let f_cut = 5.0;
+
+    let mut pt2 = filters::make_pt2(f_cut, dt);
+    let mut pt3 = filters::make_pt3(f_cut, dt);
+
+    let mut y2_prev = 0.0;
+    let mut y3_prev = 0.0;
+
+    for _ in 0..20 * time_constant_samples(f_cut, dt) {
+
+        let y2 = pt2.apply(1.0);
+        let y3 = pt3.apply(1.0);
+
+        assert!(y2 >= y2_prev && y3 >= y3_prev);
+
+        y2_prev = y2;
+        y3_prev = y3;
+    }
+
+    assert!((y2_prev - 1.0).abs() < 1e-3, "y2 = {}", y2_prev);
+    assert!((y3_prev - 1.0).abs() < 1e-3, "y3 = {}", y3_prev);
+}
+
+#[test]
+fn higher_order_filters_lag_lower_order_ones() {
+
+    let dt = LoopConfig { rate_hz: 1_000 }.dt();
+
+    let f_cut = 5.0;
+
+    let mut pt1 = filters::make_pt1(f_cut, dt);
+    let mut pt2 = filters::make_pt2(f_cut, dt);
+    let mut pt3 = filters::make_pt3(f_cut, dt);
+
+    let (mut y1, mut y2, mut y3) = (0.0, 0.0, 0.0);
+
+    for _ in 0..time_constant_samples(f_cut, dt) / 2 {
+        y1 = pt1.apply(1.0);
+        y2 = pt2.apply(1.0);
+        y3 = pt3.apply(1.0);
+    }
+
+    assert!(y1 > y2 && y2 > y3, "{} {} {}", y1, y2, y3);
+}
+
+#[test]
+fn biquad_lowpass_frequency_response() {
+
+    let dt = LoopConfig { rate_hz: 8_000 }.dt();
+
+    let f_cut = 100.0;
+
+    let gain = |freq: f32| {
+        let mut filter = filters::make_biquad_lowpass(f_cut, dt);
+        sine_gain(&mut |x| filter.apply(x), freq, dt)
+    };
+
+    assert!((gain(f_cut / 10.0) - 1.0).abs() < 0.01);
+    assert!((gain(f_cut) - 1.0 / 2.0f32.sqrt()).abs() < 0.02);
+
+    // Second order: 40 dB/decade
+    assert!(gain(f_cut * 10.0) < 0.012);
+}
+
+#[test]
+fn biquad_notch_frequency_response() {
+
+    let dt = LoopConfig { rate_hz: 8_000 }.dt();
+
+    let gain = |freq: f32| {
+        let mut filter = filters::make_biquad_notch(200.0, 160.0, dt);
+        sine_gain(&mut |x| filter.apply(x), freq, dt)
+    };
+
+    assert!(gain(200.0) < 0.05);
+    assert!(gain(50.0) > 0.95);
+    assert!(gain(800.0) > 0.95);
+}
+
+#[test]
+fn biquad_bandpass_frequency_response() {
+
+    let dt = LoopConfig { rate_hz: 8_000 }.dt();
+
+    let gain = |freq: f32| {
+        let mut filter =
+            filters::make_biquad(filters::BiquadType::Bandpass, 200.0, 2.0, dt);
+        sine_gain(&mut |x| filter.apply(x), freq, dt)
+    };
+
+    assert!((gain(200.0) - 1.0).abs() < 0.02);
+    assert!(gain(20.0) < 0.1);
+    assert!(gain(2000.0) < 0.1);
+}
+
+#[test]
+fn dynamic_notch_tracks_supplied_peak() {
+
+    let dt = LoopConfig { rate_hz: 8_000 }.dt();
+
+    let mut notch = filters::make_dynamic_notch(100.0, 600.0, 3.0, dt);
+
+    notch.set_center(300.0);
+    assert!(sine_gain(&mut |x| notch.apply(x), 300.0, dt) < 0.05);
+
+    notch.set_center(450.0);
+    assert!(sine_gain(&mut |x| notch.apply(x), 450.0, dt) < 0.05);
+    assert!(sine_gain(&mut |x| notch.apply(x), 300.0, dt) > 0.5);
+}
+
+#[test]
+fn dynamic_notch_center_is_constrained_to_band() {
+
+    let dt = LoopConfig { rate_hz: 1_000 }.dt();
+
+    let mut notch = filters::make_dynamic_notch(100.0, 600.0, 3.0, dt);
+
+    notch.set_center(20.0);
+    assert_eq!(notch.center(), 100.0);
+
+    // Band maximum lies above Nyquist at this loop rate
+    notch.set_center(550.0);
+    assert!((notch.center() - 450.0).abs() < 1e-3);
+}
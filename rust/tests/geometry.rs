@@ -0,0 +1,167 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::MAX_MOTORS;
+use hackflight::mixers::MotorMix;
+use hackflight::mixers::geometry;
+use hackflight::mixers::geometry::Rotor;
+use hackflight::mixers::hexp;
+use hackflight::mixers::hexx;
+use hackflight::mixers::octox;
+use hackflight::mixers::quadp;
+use hackflight::mixers::quadx;
+use hackflight::mixers::quadxbf;
+use hackflight::mixers::x8;
+
+// The presets' roll and pitch columns are (scaled) arm positions
+fn rotors_from(table: &[MotorMix]) -> Vec<Rotor> {
+
+    table
+        .iter()
+        .map(|row| geometry::make_rotor(-row.pitch, -row.roll, row.yaw > 0.0))
+        .collect()
+}
+
+fn mix_table(rotors: &[Rotor]) -> Vec<MotorMix> {
+
+    geometry::make(rotors).unwrap().table().to_vec()
+}
+
+// Thrust, roll, pitch and yaw from each column of the mixer
+fn effects(rotors: &[Rotor], table: &[MotorMix]) -> [[f32; 4]; 4] {
+
+    let columns: [fn(&MotorMix) -> f32; 4] =
+        [|m| m.throttle, |m| m.roll, |m| m.pitch, |m| m.yaw];
+
+    columns.map(|column| {
+
+        let mut effect = [0.0; 4];
+
+        for (rotor, row) in rotors.iter().zip(table) {
+
+            let value = column(row);
+
+            effect[0] += value * rotor.thrust;
+            effect[1] -= value * rotor.y * rotor.thrust;
+            effect[2] -= value * rotor.x * rotor.thrust;
+            effect[3] += value * if rotor.clockwise { rotor.torque } else { -rotor.torque };
+        }
+
+        effect
+    })
+}
+
+fn assert_decoupled(rotors: &[Rotor]) {
+
+    let effects = effects(rotors, &mix_table(rotors));
+
+    for (j, effect) in effects.iter().enumerate() {
+        for (k, value) in effect.iter().enumerate() {
+            if j == k {
+                assert!(*value > 0.0, "{:?}", effects);
+            } else {
+                assert!(value.abs() < 1e-4, "{:?}", effects);
+            }
+        }
+    }
+}
+
+#[test]
+fn symmetric_frames_match_presets() {
+
+    for table in [&quadxbf::MOTORS[..], &quadx::MOTORS, &quadp::MOTORS, &hexx::MOTORS,
+                  &hexp::MOTORS, &octox::MOTORS, &x8::MOTORS] {
+
+        for (actual, expected) in mix_table(&rotors_from(table)).iter().zip(table) {
+
+            let [a, e] = [actual, expected].map(|m| [m.throttle, m.roll, m.pitch, m.yaw]);
+
+            for (a, e) in a.iter().zip(e) {
+                assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn stretched_x_keeps_equal_roll_and_pitch_authority() {
+
+    // Twice as long as it is wide, Betaflight motor order
+    let rotors = [
+        geometry::make_rotor(-2.0, 1.0, true),
+        geometry::make_rotor(2.0, 1.0, false),
+        geometry::make_rotor(-2.0, -1.0, false),
+        geometry::make_rotor(2.0, -1.0, true)
+    ];
+
+    assert_decoupled(&rotors);
+
+    let table = mix_table(&rotors);
+
+    // The shorter roll arms need the larger roll demands
+    assert!((table[0].roll.abs() - 1.0).abs() < 1e-5);
+    assert!((table[0].pitch.abs() - 0.5).abs() < 1e-5);
+}
+
+#[test]
+fn asymmetric_frames_are_decoupled() {
+
+    // Dead-cat: front arms swept out, rear arms tucked in
+    let dead_cat = [
+        geometry::make_rotor(-0.08, 0.06, true),
+        geometry::make_rotor(0.1, 0.12, false),
+        geometry::make_rotor(-0.08, -0.06, false),
+        geometry::make_rotor(0.1, -0.12, true)
+    ];
+
+    assert_decoupled(&dead_cat);
+
+    // Lopsided hex with one weaker motor and its center of mass off to the side
+    let mut lopsided: Vec<Rotor> = rotors_from(&hexx::MOTORS)
+        .into_iter()
+        .map(|rotor| Rotor { y: rotor.y + 0.2, ..rotor })
+        .collect();
+
+    lopsided[2] = Rotor { thrust: 0.8, torque: 0.7, ..lopsided[2] };
+
+    assert_decoupled(&lopsided);
+
+    // Every motor pushes up on pure throttle
+    let hover = mix_table(&lopsided);
+
+    assert!(hover.iter().all(|row| row.throttle > 0.0 && row.throttle <= 1.0));
+}
+
+#[test]
+fn uncontrollable_frames_are_rejected() {
+
+    let quad = rotors_from(&quadxbf::MOTORS);
+
+    // No yaw authority with every motor spinning the same way
+    let same_spin: Vec<Rotor> = quad.iter().map(|r| Rotor { clockwise: true, ..*r }).collect();
+
+    assert!(geometry::make(&same_spin).is_none());
+
+    // No roll authority with every motor on the center line
+    let inline: Vec<Rotor> = quad.iter().map(|r| Rotor { y: 0.0, ..*r }).collect();
+
+    assert!(geometry::make(&inline).is_none());
+
+    assert!(geometry::make(&[]).is_none());
+    assert!(geometry::make(&[quad[0]; MAX_MOTORS + 1]).is_none());
+}
@@ -0,0 +1,248 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::VehicleState;
+use hackflight::clock::LoopConfig;
+use hackflight::imu;
+
+const RATE_HZ: u32 = 1_000;
+
+// Attitude is fused at 100 Hz
+const ATTITUDE_PERIOD: u32 = 10;
+
+const GYRO_BIAS: [i16; 3] = [12, -7, 3];
+
+const COUNTS_PER_DPS: f32 = 32768.0 / (imu::DEFAULT_GYRO_SCALE_DPS as f32);
+const COUNTS_PER_G: f32 = 32768.0 / (imu::DEFAULT_ACCEL_SCALE_G as f32);
+
+struct Sim {
+
+    imu: imu::Imu,
+    vstate: VehicleState,
+    usec: u32,
+    step: u32,
+
+    // True Euler angles in degrees, for single-axis motion
+    angles: [f32; 3]
+}
+
+fn make_sim() -> Sim {
+
+    Sim {
+        imu: imu::make(
+                 &LoopConfig { rate_hz: RATE_HZ },
+                 imu::Rotation::Cw0,
+                 imu::DEFAULT_GYRO_SCALE_DPS,
+                 imu::DEFAULT_ACCEL_SCALE_G).unwrap(),
+        vstate: VehicleState {
+            x: 0.0, dx: 0.0, y: 0.0, dy: 0.0, z: 0.0, dz: 0.0,
+            phi: 0.0, dphi: 0.0, theta: 0.0, dtheta: 0.0, psi: 0.0, dpsi: 0.0
+        },
+        usec: 0,
+        step: 0,
+        angles: [0.0; 3]
+    }
+}
+
+impl Sim {
+
+    // Rotates at the given body rates (deg/sec) with a small sensor jitter
+    fn run(&mut self, rates: [f32; 3], seconds: f32) {
+
+        let steps = (seconds * RATE_HZ as f32) as u32;
+
+        for _ in 0..steps {
+
+            let jitter = [1, -1, 0, 2, -2][(self.step % 5) as usize];
+
+            let mut raw_gyro = [0i16; 3];
+
+            for k in 0..3 {
+                raw_gyro[k] = (rates[k] * COUNTS_PER_DPS).round() as i16 + GYRO_BIAS[k] + jitter;
+                self.angles[k] += rates[k] / (RATE_HZ as f32);
+            }
+
+            // Gravity in body frame, for rotation about a single axis
+            let (phi, theta) = (self.angles[0].to_radians(), self.angles[1].to_radians());
+            let accel = [-theta.sin(), phi.sin(), phi.cos() * theta.cos()];
+            let raw_accel = accel.map(|a| (a * COUNTS_PER_G).round() as i16);
+
+            self.imu.update_gyro(&raw_gyro, &mut self.vstate);
+            self.imu.update_accelerometer(&raw_accel);
+
+            self.usec += 1_000_000 / RATE_HZ;
+            self.step += 1;
+
+            if self.step.is_multiple_of(ATTITUDE_PERIOD) {
+                self.imu.update_attitude(self.usec, &mut self.vstate);
+            }
+        }
+    }
+
+    fn calibrate(&mut self) {
+
+        self.run([0.0; 3], 1.5);
+
+        assert!(!self.imu.gyro_is_calibrating());
+    }
+}
+
+#[test]
+fn gyro_bias_is_removed_by_calibration() {
+
+    let mut sim = make_sim();
+
+    assert!(sim.imu.gyro_is_calibrating());
+
+    sim.calibrate();
+
+    sim.run([0.0; 3], 1.0);
+
+    let v = sim.vstate;
+
+    for rate in [v.dphi, v.dtheta, v.dpsi] {
+        assert!(rate.abs() < 0.1, "rate = {}", rate);
+    }
+
+    for angle in [v.phi, v.theta] {
+        assert!(angle.abs() < 0.5, "angle = {}", angle);
+    }
+}
+
+#[test]
+fn movement_restarts_calibration() {
+
+    let mut sim = make_sim();
+
+    // Rocking back and forth during calibration
+    for _ in 0..4 {
+        sim.run([20.0, 0.0, 0.0], 0.2);
+        sim.run([-20.0, 0.0, 0.0], 0.2);
+    }
+
+    assert!(sim.imu.gyro_is_calibrating());
+
+    // Held still, calibration eventually completes
+    sim.run([0.0; 3], 2.6);
+
+    assert!(!sim.imu.gyro_is_calibrating());
+}
+
+#[test]
+fn gyro_rates_track_rotation() {
+
+    let mut sim = make_sim();
+
+    sim.calibrate();
+
+    sim.run([30.0, -60.0, 90.0], 0.1);
+
+    let v = sim.vstate;
+
+    assert!((v.dphi - 30.0).abs() < 0.5, "dphi = {}", v.dphi);
+    assert!((v.dtheta + 60.0).abs() < 0.5, "dtheta = {}", v.dtheta);
+    assert!((v.dpsi - 90.0).abs() < 0.5, "dpsi = {}", v.dpsi);
+}
+
+#[test]
+fn roll_rotation_is_integrated() {
+
+    let mut sim = make_sim();
+
+    sim.calibrate();
+
+    sim.run([90.0, 0.0, 0.0], 0.5);
+    sim.run([0.0; 3], 0.5);
+
+    assert!((sim.vstate.phi - 45.0).abs() < 1.0, "phi = {}", sim.vstate.phi);
+    assert!(sim.vstate.theta.abs() < 1.0, "theta = {}", sim.vstate.theta);
+}
+
+#[test]
+fn pitch_rotation_is_integrated() {
+
+    let mut sim = make_sim();
+
+    sim.calibrate();
+
+    sim.run([0.0, 60.0, 0.0], 0.5);
+    sim.run([0.0; 3], 0.5);
+
+    assert!((sim.vstate.theta - 30.0).abs() < 1.0, "theta = {}", sim.vstate.theta);
+    assert!(sim.vstate.phi.abs() < 1.0, "phi = {}", sim.vstate.phi);
+}
+
+#[test]
+fn yaw_rotation_is_integrated() {
+
+    let mut sim = make_sim();
+
+    sim.calibrate();
+
+    sim.run([0.0, 0.0, 90.0], 1.0);
+    sim.run([0.0; 3], 0.2);
+
+    assert!((sim.vstate.psi - 90.0).abs() < 1.0, "psi = {}", sim.vstate.psi);
+
+    // Heading is reported in [0, 360)
+    sim.run([0.0, 0.0, -90.0], 2.0);
+    sim.run([0.0; 3], 0.2);
+
+    assert!((sim.vstate.psi - 270.0).abs() < 1.0, "psi = {}", sim.vstate.psi);
+}
+
+#[test]
+fn accelerometer_corrects_attitude() {
+
+    let mut sim = make_sim();
+
+    sim.calibrate();
+
+    // Tilted, but gyro reports no motion
+    sim.angles[0] = 30.0;
+    sim.run([0.0; 3], 2.0);
+
+    assert!((sim.vstate.phi - 30.0).abs() < 1.0, "phi = {}", sim.vstate.phi);
+}
+
+#[test]
+fn first_update_ignores_uptime() {
+
+    let mut sim = make_sim();
+
+    // Powered up a minute ago, tilted
+    sim.usec = 60_000_000;
+    sim.angles[0] = 30.0;
+
+    sim.run([0.0; 3], 0.05);
+
+    // Only starting to level toward the accelerometer
+    assert!(sim.vstate.phi > 0.0 && sim.vstate.phi < 30.0, "phi = {}", sim.vstate.phi);
+}
+
+#[test]
+fn zero_loop_rate_is_rejected() {
+
+    let imu = imu::make(
+        &LoopConfig { rate_hz: 0 },
+        imu::Rotation::Cw0,
+        imu::DEFAULT_GYRO_SCALE_DPS,
+        imu::DEFAULT_ACCEL_SCALE_G);
+
+    assert_eq!(imu.err().unwrap().field, "rate_hz");
+}
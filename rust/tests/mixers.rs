@@ -0,0 +1,236 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::Demands;
+use hackflight::MAX_MOTORS;
+use hackflight::Mixer;
+use hackflight::VehicleState;
+use hackflight::mixers;
+use hackflight::mixers::MixerType;
+use hackflight::mixers::MotorMix;
+use hackflight::mixers::hexp;
+use hackflight::mixers::hexx;
+use hackflight::mixers::octox;
+use hackflight::mixers::quadp;
+use hackflight::mixers::quadx;
+use hackflight::mixers::quadxbf;
+use hackflight::mixers::quadxbf::QuadXbf;
+use hackflight::mixers::saturation;
+use hackflight::mixers::saturation::Priority;
+use hackflight::mixers::saturation::SaturationConfig;
+use hackflight::mixers::tricopter;
+use hackflight::mixers::x8;
+use hackflight::mixers::y6;
+use hackflight::step;
+
+const DEMANDS: Demands = Demands { throttle: 0.5, roll: 0.1, pitch: -0.2, yaw: 0.05 };
+
+// Flat hexacopter, motors every 60 degrees from the front, alternating
+// spin direction
+fn hex() -> [MotorMix; 6] {
+
+    let mut table = [MotorMix { throttle: 1.0, roll: 0.0, pitch: 0.0, yaw: 0.0 }; 6];
+
+    for (k, row) in table.iter_mut().enumerate() {
+
+        let angle = (k as f32 * 60.0).to_radians();
+
+        row.roll = -angle.sin();
+        row.pitch = -angle.cos();
+        row.yaw = if k % 2 == 0 { 1.0 } else { -1.0 };
+    }
+
+    table
+}
+
+#[test]
+fn quadxbf_preset_matches_original_mix() {
+
+    let d = &DEMANDS;
+
+    let motors = QuadXbf { saturation: saturation::DEFAULT_CONFIG }.get_motors(d);
+
+    assert_eq!(motors.as_slice(), [
+        d.throttle - d.roll + d.pitch + d.yaw,
+        d.throttle - d.roll - d.pitch - d.yaw,
+        d.throttle + d.roll + d.pitch - d.yaw,
+        d.throttle + d.roll - d.pitch + d.yaw
+    ]);
+
+    assert_eq!(mixers::make_matrix(&quadxbf::MOTORS).unwrap().get_motors(d), motors);
+}
+
+#[test]
+fn matrix_mixes_any_motor_count() {
+
+    let mixer = mixers::make_matrix(&hex()).unwrap();
+
+    assert_eq!(mixer.motor_count(), 6);
+
+    let motors = mixer.get_motors(&DEMANDS);
+
+    assert_eq!(motors.count, 6);
+
+    // Rows the table doesn't use stay zero
+    assert!(motors.values[6..].iter().all(|&value| value == 0.0));
+
+    // Level throttle alone spins every motor equally
+    let hover = mixer.get_motors(&Demands { throttle: 0.5, roll: 0.0, pitch: 0.0, yaw: 0.0 });
+
+    assert_eq!(hover.as_slice(), [0.5; 6]);
+}
+
+#[test]
+fn make_matrix_rejects_bad_tables() {
+
+    let row = MotorMix { throttle: 1.0, roll: 0.0, pitch: 0.0, yaw: 0.0 };
+
+    assert!(mixers::make_matrix(&[]).is_none());
+    assert!(mixers::make_matrix(&[row; MAX_MOTORS + 1]).is_none());
+    assert!(mixers::make_matrix(&[row; MAX_MOTORS]).is_some());
+}
+
+#[test]
+fn step_uses_mixer_motor_count() {
+
+    let vstate = VehicleState {
+        x: 0.0, dx: 0.0, y: 0.0, dy: 0.0, z: 0.0, dz: 0.0,
+        phi: 0.0, dphi: 0.0, theta: 0.0, dtheta: 0.0, psi: 0.0, dpsi: 0.0
+    };
+
+    let mixer = mixers::make_matrix(&hex()).unwrap();
+
+    let armed = step(&DEMANDS, &vstate, &mut [], &false, &true, &0, &mixer);
+
+    assert_eq!(armed, mixer.get_motors(&DEMANDS));
+
+    let disarmed = step(&DEMANDS, &vstate, &mut [], &false, &false, &0, &mixer);
+
+    assert_eq!(disarmed.as_slice(), [0.0; 6]);
+}
+
+const PRESETS: [(MixerType, &[MotorMix]); 9] = [
+    (MixerType::QuadXbf, &quadxbf::MOTORS),
+    (MixerType::QuadX, &quadx::MOTORS),
+    (MixerType::QuadP, &quadp::MOTORS),
+    (MixerType::HexX, &hexx::MOTORS),
+    (MixerType::HexP, &hexp::MOTORS),
+    (MixerType::Y6, &y6::MOTORS),
+    (MixerType::OctoX, &octox::MOTORS),
+    (MixerType::X8, &x8::MOTORS),
+    (MixerType::Tricopter, &tricopter::MOTORS)
+];
+
+// Net roll, pitch and yaw torque of the motors, in units of the table
+fn torques(table: &[MotorMix], demands: &Demands) -> [f32; 3] {
+
+    let motors = mixers::mix(table, demands);
+
+    let mut torques = [0.0; 3];
+
+    for (value, row) in motors.as_slice().iter().zip(table) {
+        torques[0] += value * row.roll;
+        torques[1] += value * row.pitch;
+        torques[2] += value * row.yaw;
+    }
+
+    torques
+}
+
+#[test]
+fn presets_have_no_torque_at_pure_throttle() {
+
+    let hover = Demands { throttle: 0.5, roll: 0.0, pitch: 0.0, yaw: 0.0 };
+
+    for (mixer_type, table) in PRESETS {
+
+        let motors = mixer_type.mixer(&saturation::DEFAULT_CONFIG).get_motors(&hover);
+
+        assert_eq!(motors.as_slice(), mixers::mix(table, &hover).as_slice(), "{:?}", mixer_type);
+        assert_eq!(motors.servo, 0.0, "{:?}", mixer_type);
+
+        for torque in torques(table, &hover) {
+            assert!(torque.abs() < 1e-5, "{:?}: {}", mixer_type, torque);
+        }
+    }
+}
+
+#[test]
+fn presets_torque_follows_demands() {
+
+    let axis = |roll, pitch, yaw| Demands { throttle: 0.5, roll, pitch, yaw };
+
+    for (mixer_type, table) in PRESETS {
+
+        let [roll, _, _] = torques(table, &axis(0.1, 0.0, 0.0));
+        let [_, pitch, _] = torques(table, &axis(0.0, 0.1, 0.0));
+        let [r, p, yaw] = torques(table, &axis(0.0, 0.0, 0.1));
+
+        assert!(roll > 0.0 && pitch > 0.0, "{:?}", mixer_type);
+
+        // Yaw comes from the motors alone, except on the tricopter
+        if mixer_type == MixerType::Tricopter {
+            assert_eq!(yaw, 0.0);
+        } else {
+            assert!(yaw > 0.0 && r.abs() < 1e-5 && p.abs() < 1e-5, "{:?}", mixer_type);
+        }
+    }
+}
+
+#[test]
+fn presets_limit_the_motors_as_configured() {
+
+    // Roll and yaw together need more than the motors' range
+    let d = Demands { throttle: 0.9, roll: 0.3, pitch: 0.0, yaw: 0.3 };
+
+    let equal = SaturationConfig { priority: Priority::Equal, airmode: true };
+
+    for (mixer_type, table) in PRESETS {
+
+        let motors = mixer_type.mixer(&equal).get_motors(&d);
+
+        assert_eq!(motors.as_slice(), saturation::mix_limited(table, &d, &equal).as_slice(), "{:?}", mixer_type);
+    }
+
+    let quad = |config| MixerType::QuadXbf.mixer(config).get_motors(&d);
+
+    assert_ne!(quad(&equal), quad(&saturation::DEFAULT_CONFIG));
+}
+
+#[test]
+fn tricopter_servo_follows_yaw() {
+
+    let mixer = MixerType::Tricopter.mixer(&saturation::DEFAULT_CONFIG);
+
+    let yaw = |yaw| mixer.get_motors(&Demands { throttle: 0.5, roll: 0.0, pitch: 0.0, yaw });
+
+    assert_eq!(yaw(0.25).servo, 0.25);
+    assert_eq!(yaw(-2.0).servo, -1.0);
+    assert_eq!(yaw(0.25).as_slice(), yaw(0.0).as_slice());
+}
+
+#[test]
+fn ardupilot_quadx_reorders_betaflight_quadx() {
+
+    // ArduPilot/PX4 motor k is Betaflight motor ORDER[k]
+    const ORDER: [usize; 4] = [1, 2, 3, 0];
+
+    for (k, row) in quadx::MOTORS.iter().enumerate() {
+        assert_eq!(*row, quadxbf::MOTORS[ORDER[k]]);
+    }
+}
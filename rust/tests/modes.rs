@@ -0,0 +1,236 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::Demands;
+use hackflight::VehicleState;
+use hackflight::failsafe;
+use hackflight::mixers::quadxbf::QuadXbf;
+use hackflight::mixers::saturation;
+use hackflight::modes;
+use hackflight::modes::FlightMode;
+use hackflight::modes::ModeRange;
+use hackflight::modes::Modes;
+use hackflight::pids;
+use hackflight::pids::LevelMode;
+use hackflight::receiver;
+use hackflight::receiver::Receiver;
+use hackflight::step;
+
+const QUAD: QuadXbf = QuadXbf { saturation: saturation::DEFAULT_CONFIG };
+
+fn receiver_with_aux(aux1: u16, aux2: u16) -> Receiver {
+
+    let mut receiver = receiver::make();
+
+    receiver.set_values(&[1000, 1500, 1500, 1500, aux1, aux2], 0, false, 1000, 2000);
+
+    receiver
+}
+
+// Angle on aux1 high, Horizon on aux1 mid, AltHold on aux2 high
+fn make_modes() -> Modes {
+
+    let mut modes = modes::make();
+
+    assert!(modes.add_range(ModeRange { mode: FlightMode::Angle, aux: 0, start: 1700, end: 2100 }));
+    assert!(modes.add_range(ModeRange { mode: FlightMode::Horizon, aux: 0, start: 1300, end: 1700 }));
+    assert!(modes.add_range(ModeRange { mode: FlightMode::AltHold, aux: 1, start: 1700, end: 2100 }));
+
+    modes
+}
+
+fn alt_hold() -> pids::Controller {
+
+    pids::make_alt_hold(0.75, 1.5, &pids::DEFAULT_ALT_HOLD_CONFIG).unwrap()
+}
+
+fn vstate(z: f32, dz: f32) -> VehicleState {
+
+    VehicleState {
+        x: 0.0, dx: 0.0, y: 0.0, dy: 0.0, z, dz,
+        phi: 0.0, dphi: 0.0, theta: 0.0, dtheta: 0.0, psi: 0.0, dpsi: 0.0
+    }
+}
+
+#[test]
+fn aux_ranges_select_modes() {
+
+    let failsafe = failsafe::make(failsafe::DEFAULT_CONFIG).unwrap();
+
+    let mut modes = make_modes();
+
+    assert_eq!(modes.update(&receiver_with_aux(1000, 1000), &failsafe, true), FlightMode::Acro);
+    assert_eq!(modes.level_mode(), LevelMode::Acro);
+
+    assert_eq!(modes.update(&receiver_with_aux(1500, 1000), &failsafe, true), FlightMode::Horizon);
+    assert_eq!(modes.level_mode(), LevelMode::Horizon);
+
+    assert_eq!(modes.update(&receiver_with_aux(2000, 1000), &failsafe, true), FlightMode::Angle);
+    assert_eq!(modes.level_mode(), LevelMode::Angle);
+
+    // AltHold combines with the attitude mode
+    assert_eq!(modes.update(&receiver_with_aux(1000, 2000), &failsafe, true), FlightMode::AltHold);
+    assert!(modes.is_active(FlightMode::Acro));
+
+    // ... but needs a valid altitude
+    assert_eq!(modes.update(&receiver_with_aux(1000, 2000), &failsafe, false), FlightMode::Acro);
+}
+
+#[test]
+fn angle_has_priority_over_horizon() {
+
+    let failsafe = failsafe::make(failsafe::DEFAULT_CONFIG).unwrap();
+
+    let mut modes = modes::make();
+
+    modes.add_range(ModeRange { mode: FlightMode::Horizon, aux: 0, start: 900, end: 2100 });
+    modes.add_range(ModeRange { mode: FlightMode::Angle, aux: 1, start: 1700, end: 2100 });
+
+    modes.update(&receiver_with_aux(1500, 2000), &failsafe, true);
+
+    assert!(modes.is_active(FlightMode::Angle));
+    assert!(!modes.is_active(FlightMode::Horizon));
+}
+
+#[test]
+fn pos_hold_and_failsafe_imply_angle_and_alt_hold() {
+
+    let mut modes = modes::make();
+
+    modes.add_range(ModeRange { mode: FlightMode::PosHold, aux: 1, start: 1700, end: 2100 });
+
+    let mut failsafe = failsafe::make(failsafe::DEFAULT_CONFIG).unwrap();
+
+    assert_eq!(modes.update(&receiver_with_aux(1000, 2000), &failsafe, true), FlightMode::PosHold);
+    assert!(modes.is_active(FlightMode::Angle));
+    assert!(modes.is_active(FlightMode::AltHold));
+
+    // Without an altitude, PosHold can only level
+    assert_eq!(modes.update(&receiver_with_aux(1000, 2000), &failsafe, false), FlightMode::Angle);
+
+    // Signal lost long enough for failsafe to start landing
+    failsafe.update(0, true);
+    failsafe.update(10_000, false);
+    failsafe.update(10_000 + failsafe::DEFAULT_CONFIG.guard_usec, false);
+
+    assert_eq!(modes.update(&receiver_with_aux(1000, 1000), &failsafe, true), FlightMode::Failsafe);
+    assert_eq!(modes.level_mode(), LevelMode::Angle);
+    assert!(modes.is_active(FlightMode::AltHold));
+
+    modes.update(&receiver_with_aux(1000, 1000), &failsafe, false);
+    assert!(!modes.is_active(FlightMode::AltHold));
+}
+
+#[test]
+fn add_range_rejects_bad_aux_and_overflow() {
+
+    let mut modes = modes::make();
+
+    assert!(!modes.add_range(
+            ModeRange { mode: FlightMode::Angle, aux: receiver::AUX_COUNT, start: 900, end: 2100 }));
+
+    for _ in 0..modes::MAX_RANGES {
+        assert!(modes.add_range(ModeRange { mode: FlightMode::Angle, aux: 0, start: 900, end: 2100 }));
+    }
+
+    assert!(!modes.add_range(ModeRange { mode: FlightMode::Angle, aux: 0, start: 900, end: 2100 }));
+}
+
+#[test]
+fn alt_hold_engages_without_throttle_bump() {
+
+    let failsafe = failsafe::make(failsafe::DEFAULT_CONFIG).unwrap();
+
+    let mut modes = make_modes();
+
+    let mut pids = [alt_hold()];
+
+    // Hovering at mid-stick, sinking slightly
+    let demands = Demands { throttle: 0.5, roll: 0.0, pitch: 0.0, yaw: 0.0 };
+    let state = vstate(2.0, -0.1);
+
+    modes.update(&receiver_with_aux(1000, 1000), &failsafe, true);
+
+    let before = modes.step(&demands, &state, &mut pids, &false, &true, &0, &QUAD).unwrap();
+
+    assert_eq!(before.values[0], 0.5);
+
+    modes.update(&receiver_with_aux(1000, 2000), &failsafe, true);
+
+    let after = modes.step(&demands, &state, &mut pids, &false, &true, &1000, &QUAD).unwrap();
+
+    assert!((after.values[0] - before.values[0]).abs() < 1e-6, "{} != {}", after.values[0], before.values[0]);
+
+    // A controller switched in cold would kick the throttle
+    let mut cold = [alt_hold()];
+
+    let kicked = step(&demands, &state, &mut cold, &false, &true, &1000, &QUAD);
+
+    assert!((kicked.values[0] - before.values[0]).abs() > 0.05);
+}
+
+#[test]
+fn disarmed_step_zeroes_motors() {
+
+    let mut modes = modes::make();
+
+    let mut pids = [alt_hold()];
+
+    let demands = Demands { throttle: 0.5, roll: 0.0, pitch: 0.0, yaw: 0.0 };
+
+    let motors = modes.step(&demands, &vstate(0.0, 0.0), &mut pids, &false, &false, &0, &QUAD).unwrap();
+
+    assert_eq!(motors.as_slice(), [0.0; 4]);
+}
+
+#[test]
+fn alt_hold_on_from_the_start_takes_the_stick_throttle() {
+
+    let failsafe = failsafe::make(failsafe::DEFAULT_CONFIG).unwrap();
+
+    let mut modes = make_modes();
+
+    let mut pids = [alt_hold()];
+
+    let demands = Demands { throttle: 0.5, roll: 0.0, pitch: 0.0, yaw: 0.0 };
+
+    modes.update(&receiver_with_aux(1000, 2000), &failsafe, true);
+
+    let motors = modes.step(&demands, &vstate(2.0, -0.1), &mut pids, &false, &true, &0, &QUAD)
+        .unwrap();
+
+    assert!((motors.values[0] - 0.5).abs() < 1e-6, "{}", motors.values[0]);
+}
+
+#[test]
+fn too_many_controllers_are_refused() {
+
+    let mut modes = modes::make();
+
+    let mut pids: Vec<_> = (0..=modes::MAX_CONTROLLERS).map(|_| alt_hold()).collect();
+
+    let demands = Demands { throttle: 0.5, roll: 0.0, pitch: 0.0, yaw: 0.0 };
+
+    let result = modes.step(&demands, &vstate(0.0, 0.0), &mut pids, &false, &true, &0, &QUAD);
+
+    assert_eq!(result.err().unwrap().field, "controllers");
+
+    pids.pop();
+
+    assert!(modes.step(&demands, &vstate(0.0, 0.0), &mut pids, &false, &true, &0, &QUAD).is_ok());
+}
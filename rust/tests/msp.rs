@@ -0,0 +1,293 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::msp;
+use hackflight::msp::Direction;
+use hackflight::msp::Payload;
+use hackflight::msp::Version;
+use hackflight::msp::messages;
+
+const MSP_ATTITUDE: u16 = 108;
+const MSP_SET_MOTOR: u16 = 214;
+
+// Parses a stream, returning (version, direction, function, payload) for
+// each message received
+fn parse_all(bytes: &[u8]) -> Vec<(Version, Direction, u16, Vec<u8>)> {
+
+    let mut parser = msp::make_parser();
+
+    let mut messages = Vec::new();
+
+    for &c in bytes {
+        if let Some(msg) = parser.parse(c) {
+            messages.push((msg.version, msg.direction, msg.function, msg.payload().to_vec()));
+        }
+    }
+
+    assert!(parser.is_idle());
+
+    messages
+}
+
+#[test]
+fn shorts_are_framed_like_the_cpp_serializer() {
+
+    let mut serializer = msp::make_serializer();
+
+    assert!(serializer.serialize_shorts(
+            Version::V1, Direction::Response, MSP_ATTITUDE, &[1, -2, 300]));
+
+    let checksum = 6 ^ 108 ^ 0x01 ^ 0xFE ^ 0xFF ^ 0x2C ^ 0x01;
+
+    assert_eq!(
+        serializer.bytes(),
+        &[b'$', b'M', b'>', 6, 108, 0x01, 0x00, 0xFE, 0xFF, 0x2C, 0x01, checksum]);
+
+    // Byte-by-byte reads drain the same frame
+    let mut read = Vec::new();
+
+    while serializer.available() > 0 {
+        read.push(serializer.read());
+    }
+
+    assert_eq!(read, serializer.bytes());
+}
+
+#[test]
+fn v1_request_without_payload_is_parsed() {
+
+    // As sent by the Python and Java parsers
+    let messages = parse_all(&[b'$', b'M', b'<', 0, 108, 108]);
+
+    assert_eq!(messages, vec![(Version::V1, Direction::Request, MSP_ATTITUDE, vec![])]);
+}
+
+#[test]
+fn v2_frame_matches_reference() {
+
+    // MSP_API_VERSION request from the MSPv2 specification
+    let frame = [b'$', b'X', b'<', 0x00, 0x64, 0x00, 0x00, 0x00, 0x8F];
+
+    let mut serializer = msp::make_serializer();
+
+    assert!(serializer.serialize_bytes(Version::V2, Direction::Request, 100, &[]));
+
+    assert_eq!(serializer.bytes(), &frame);
+
+    assert_eq!(parse_all(&frame), vec![(Version::V2, Direction::Request, 100, vec![])]);
+}
+
+#[test]
+fn shorts_round_trip_in_both_versions() {
+
+    let motors = [1000, 1500, -1, i16::MAX];
+
+    for version in [Version::V1, Version::V2] {
+
+        let mut serializer = msp::make_serializer();
+
+        assert!(serializer.serialize_shorts(version, Direction::Request, MSP_SET_MOTOR, &motors));
+
+        let mut parser = msp::make_parser();
+
+        let mut parsed = None;
+
+        for &c in serializer.bytes() {
+            if let Some(msg) = parser.parse(c) {
+                parsed = Some((0..motors.len()).map(|k| msg.parse_short(k)).collect::<Vec<_>>());
+            }
+        }
+
+        assert_eq!(parsed, Some(motors.to_vec()), "{:?}", version);
+    }
+}
+
+#[test]
+fn large_function_ids_need_v2() {
+
+    let mut serializer = msp::make_serializer();
+
+    assert!(!serializer.serialize_bytes(Version::V1, Direction::Request, 0x1F01, &[1]));
+    assert_eq!(serializer.available(), 0);
+
+    assert!(serializer.serialize_bytes(Version::V2, Direction::Response, 0x1F01, &[1, 2, 3]));
+
+    let bytes = serializer.bytes().to_vec();
+
+    assert_eq!(parse_all(&bytes), vec![(Version::V2, Direction::Response, 0x1F01, vec![1, 2, 3])]);
+}
+
+#[test]
+fn oversized_payloads_are_rejected() {
+
+    let mut serializer = msp::make_serializer();
+
+    assert!(serializer.serialize_bytes(Version::V2, Direction::Request, 1, &[7; msp::BUF_SIZE]));
+    assert!(!serializer.serialize_bytes(Version::V2, Direction::Request, 1, &[7; msp::BUF_SIZE + 1]));
+
+    // A v1 frame announcing a payload bigger than the buffer is dropped
+    assert!(parse_all(&[b'$', b'M', b'<', 200, 1]).is_empty());
+}
+
+#[test]
+fn bad_checksum_is_rejected_and_parser_resyncs() {
+
+    let mut serializer = msp::make_serializer();
+
+    serializer.serialize_shorts(Version::V1, Direction::Response, MSP_ATTITUDE, &[10, 20, 30]);
+
+    let good = serializer.bytes().to_vec();
+
+    let mut corrupt = good.clone();
+    corrupt[6] ^= 0x40;
+
+    let mut stream = vec![0x00, b'$', b'Q', 0xFF];
+    stream.extend(&corrupt);
+    stream.extend(&good);
+
+    let messages = parse_all(&stream);
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].2, MSP_ATTITUDE);
+    assert_eq!(messages[0].3, good[5..11].to_vec());
+}
+
+#[test]
+fn crc8_dvb_s2_check_value() {
+
+    let crc = b"123456789".iter().fold(0, |crc, &c| msp::crc8_dvb_s2(crc, c));
+
+    assert_eq!(crc, 0xBC);
+}
+
+// Serializes a typed message and parses it back
+fn round_trip<P: Payload>(version: Version, message: &P) -> Option<P> {
+
+    let mut serializer = msp::make_serializer();
+
+    assert!(serializer.serialize(version, Direction::Response, message));
+
+    let mut parser = msp::make_parser();
+
+    let mut decoded = None;
+
+    for &c in serializer.bytes() {
+        if let Some(msg) = parser.parse(c) {
+            decoded = msg.decode::<P>();
+        }
+    }
+
+    decoded
+}
+
+#[test]
+fn typed_messages_match_json_ids_and_sizes() {
+
+    assert_eq!((messages::Rc::ID, messages::Rc::SIZE), (105, 12));
+    assert_eq!((messages::Attitude::ID, messages::Attitude::SIZE), (108, 6));
+    assert_eq!((messages::Vl53l5::ID, messages::Vl53l5::SIZE), (121, 32));
+    assert_eq!((messages::Paa3905::ID, messages::Paa3905::SIZE), (122, 4));
+    assert_eq!((messages::SetRawRc::ID, messages::SetRawRc::SIZE), (200, 12));
+    assert_eq!((messages::SetMotor::ID, messages::SetMotor::SIZE), (214, 8));
+}
+
+#[test]
+fn typed_messages_round_trip() {
+
+    let attitude = messages::Attitude { angx: -123, angy: 45, heading: 359 };
+
+    let rc = messages::SetRawRc { c1: 1000, c2: 1500, c3: 2000, c4: 1200, c5: 1800, c6: 988 };
+
+    let flow = messages::Paa3905 { x: -3, y: 17 };
+
+    let ranges = messages::Vl53l5 { p11: 1, p23: -1, p44: 4000, .. Default::default() };
+
+    for version in [Version::V1, Version::V2] {
+        assert_eq!(round_trip(version, &attitude), Some(attitude));
+        assert_eq!(round_trip(version, &rc), Some(rc));
+        assert_eq!(round_trip(version, &flow), Some(flow));
+        assert_eq!(round_trip(version, &ranges), Some(ranges));
+    }
+}
+
+#[test]
+fn typed_encoding_matches_shorts() {
+
+    let motors = messages::SetMotor { m1: 1, m2: 2, m3: 3, m4: -4 };
+
+    let mut typed = msp::make_serializer();
+    let mut shorts = msp::make_serializer();
+
+    assert!(typed.serialize(Version::V1, Direction::Request, &motors));
+    assert!(shorts.serialize_shorts(Version::V1, Direction::Request, MSP_SET_MOTOR, &[1, 2, 3, -4]));
+
+    assert_eq!(typed.bytes(), shorts.bytes());
+}
+
+#[test]
+fn typed_decoding_checks_id_and_size() {
+
+    let mut serializer = msp::make_serializer();
+    let mut parser = msp::make_parser();
+
+    // Betaflight sends 16 RC channels; we only keep the first six
+    let channels: Vec<i16> = (0..16).map(|k| 1000 + 10 * k).collect();
+
+    serializer.serialize_shorts(Version::V1, Direction::Response, 105, &channels);
+
+    let mut rc = None;
+    let mut attitude = None;
+
+    for &c in serializer.bytes() {
+        if let Some(msg) = parser.parse(c) {
+            rc = msg.decode::<messages::Rc>();
+            attitude = msg.decode::<messages::Attitude>();
+        }
+    }
+
+    assert_eq!(rc.map(|rc| [rc.c1, rc.c6]), Some([1000, 1050]));
+    assert_eq!(attitude, None);
+
+    // Too short for its type
+    serializer.serialize_shorts(Version::V1, Direction::Response, MSP_ATTITUDE, &[1, 2]);
+
+    let mut attitude = Some(messages::Attitude::default());
+
+    for &c in serializer.bytes() {
+        if let Some(msg) = parser.parse(c) {
+            attitude = msg.decode::<messages::Attitude>();
+        }
+    }
+
+    assert_eq!(attitude, None);
+}
+
+// The crate builds from its own copy of the parser's messages.json, which
+// isn't there in the packaged crate
+#[test]
+fn messages_match_parser() {
+
+    let dir = env!("CARGO_MANIFEST_DIR");
+
+    if let Ok(parser) = std::fs::read_to_string(format!("{}/../parser/messages.json", dir)) {
+
+        let ours = std::fs::read_to_string(format!("{}/messages.json", dir)).unwrap();
+
+        assert!(ours == parser, "messages.json differs from parser/messages.json; copy it over");
+    }
+}
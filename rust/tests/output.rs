@@ -0,0 +1,162 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::Motors;
+use hackflight::make_motors_off;
+use hackflight::output;
+use hackflight::output::Output;
+use hackflight::output::OutputConfig;
+use hackflight::output::Protocol;
+
+const PWM: Protocol = Protocol::Pwm { stop_usec: 1000, min_usec: 1100, max_usec: 2000 };
+
+fn make(config: OutputConfig) -> Output {
+
+    output::make(config).unwrap()
+}
+
+fn motors(values: &[f32]) -> Motors {
+
+    let mut motors = make_motors_off(values.len());
+
+    motors.values[..values.len()].copy_from_slice(values);
+
+    motors
+}
+
+fn close(a: f32, b: f32) -> bool {
+
+    (a - b).abs() < 1e-5
+}
+
+#[test]
+fn disarmed_motors_are_stopped() {
+
+    let m = motors(&[0.5, 0.6, 0.7, 0.8]);
+
+    let dshot = make(output::DEFAULT_CONFIG).get_commands(&m, false, None);
+
+    assert_eq!(dshot.as_slice(), &[output::DSHOT_STOP; 4]);
+    assert_eq!(dshot.servo_usec, output::SERVO_CENTER_USEC);
+
+    let pwm = make(OutputConfig { protocol: PWM, ..output::DEFAULT_CONFIG }).get_commands(&m, false, None);
+
+    assert_eq!(pwm.as_slice(), &[1000; 4]);
+}
+
+#[test]
+fn armed_motors_span_the_esc_range() {
+
+    let m = motors(&[0.0, 1.0, 0.5]);
+
+    // Idle at zero throttle
+    let dshot = make(output::DEFAULT_CONFIG).get_commands(&m, true, None);
+
+    assert_eq!(dshot.as_slice(), &[158, output::DSHOT_MAX, 1102]);
+
+    let pwm = OutputConfig { idle: 0.0, protocol: PWM, ..output::DEFAULT_CONFIG };
+
+    assert_eq!(make(pwm).get_commands(&m, true, None).as_slice(), &[1100, 2000, 1550]);
+}
+
+#[test]
+fn linearization_inverts_the_thrust_curve() {
+
+    let t = 0.4;
+
+    let out = make(OutputConfig { idle: 0.0, thrust_linear: t, ..output::DEFAULT_CONFIG });
+
+    let m = motors(&[0.0, 0.1, 0.25, 0.5, 0.9, 1.0]);
+
+    let conditioned = out.condition(&m, None);
+
+    for (demand, value) in m.as_slice().iter().zip(conditioned.as_slice()) {
+
+        let thrust = t * value * value + (1.0 - t) * value;
+
+        assert!(close(thrust, *demand), "{} {}", demand, thrust);
+    }
+
+    // Low values are raised, for the same thrust
+    assert!(conditioned.values[1] > 0.1);
+}
+
+#[test]
+fn sag_compensation_keeps_power_constant() {
+
+    let out = make(OutputConfig { idle: 0.0, sag_compensation: 1.0, ..output::DEFAULT_CONFIG });
+
+    let m = motors(&[0.4, 0.5]);
+
+    let full = out.condition(&m, Some(16.8));
+
+    assert_eq!(full, m);
+
+    for volts in [16.0, 15.2, 14.4] {
+
+        let sagged = out.condition(&m, Some(volts));
+
+        assert!(close(sagged.values[0] * volts, 0.4 * 16.8));
+    }
+
+    // A freshly charged pack above the reference isn't throttled back
+    assert_eq!(out.condition(&m, Some(17.4)), m);
+
+    // Unmeasured voltage
+    assert_eq!(out.condition(&m, None), m);
+    assert_eq!(out.condition(&m, Some(0.0)), m);
+
+    // The boost is capped, and so is the output
+    let flat = out.condition(&m, Some(4.0));
+
+    assert!(close(flat.values[0], 0.6));
+    assert!(close(flat.values[1], 0.75));
+    assert_eq!(out.condition(&motors(&[0.9]), Some(14.0)).values[0], 1.0);
+}
+
+#[test]
+fn servo_maps_to_microseconds() {
+
+    let mut m = motors(&[0.5, 0.5, 0.5]);
+
+    let out = make(output::DEFAULT_CONFIG);
+
+    for (servo, usec) in [(0.0, 1500), (0.5, 1750), (-0.5, 1250), (1.0, 2000), (-2.0, 1000)] {
+
+        m.servo = servo;
+
+        assert_eq!(out.get_commands(&m, true, None).servo_usec, usec);
+    }
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+
+    let d = output::DEFAULT_CONFIG;
+
+    let field = |config| output::make(config).err().unwrap().field;
+
+    assert_eq!(field(OutputConfig { idle: 0.5, ..d }), "idle");
+    assert_eq!(field(OutputConfig { thrust_linear: -0.1, ..d }), "thrust_linear");
+    assert_eq!(field(OutputConfig { sag_compensation: 1.5, ..d }), "sag_compensation");
+    assert_eq!(field(OutputConfig { reference_voltage: 0.0, ..d }), "reference_voltage");
+
+    let backwards = Protocol::Pwm { stop_usec: 1000, min_usec: 2000, max_usec: 1100 };
+
+    assert_eq!(field(OutputConfig { protocol: backwards, ..d }), "protocol");
+}
@@ -0,0 +1,135 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::Demands;
+use hackflight::VehicleState;
+use hackflight::clock::LoopConfig;
+use hackflight::pids;
+use hackflight::pids::AltHoldConfig;
+use hackflight::pids::AnglePidConfig;
+use hackflight::pids::InvalidConfig;
+use hackflight::pids::LevelMode;
+
+const LOOP_CONFIG: LoopConfig = LoopConfig { rate_hz: 1000 };
+
+fn make_angle(config: &AnglePidConfig) -> Result<pids::Controller, InvalidConfig> {
+
+    pids::make_angle(0.0125, 0.0103, 0.0000625, 0.0001756, 3.0, &LOOP_CONFIG, config)
+}
+
+fn angle_error(config: AnglePidConfig) -> &'static str {
+
+    make_angle(&config).err().expect("config should be rejected").field
+}
+
+fn alt_hold_error(config: AltHoldConfig) -> &'static str {
+
+    pids::make_alt_hold(0.75, 1.5, &config).err().expect("config should be rejected").field
+}
+
+#[test]
+fn defaults_are_valid() {
+
+    assert!(make_angle(&pids::DEFAULT_ANGLE_CONFIG).is_ok());
+    assert!(pids::make_alt_hold(0.75, 1.5, &pids::DEFAULT_ALT_HOLD_CONFIG).is_ok());
+}
+
+#[test]
+fn invalid_angle_configs_are_rejected() {
+
+    let d = pids::DEFAULT_ANGLE_CONFIG;
+
+    assert_eq!(
+        angle_error(AnglePidConfig { dterm_lpf1_dyn_max_hz: 50.0, ..d }),
+        "dterm_lpf1_dyn_max_hz");
+
+    assert_eq!(
+        angle_error(AnglePidConfig { dterm_notch_hz: 200.0, dterm_notch_cutoff_hz: 250.0, ..d }),
+        "dterm_notch_cutoff_hz");
+
+    assert_eq!(
+        angle_error(AnglePidConfig { iterm_windup_point_percent: 100.0, ..d }),
+        "iterm_windup_point_percent");
+
+    assert_eq!(angle_error(AnglePidConfig { iterm_limit: 0.0, ..d }), "iterm_limit");
+
+    assert_eq!(
+        angle_error(AnglePidConfig { level_angle_limit: 120.0, ..d }),
+        "level_angle_limit");
+
+    // A 200 Hz loop can't run the default 150 Hz D-term filters
+    assert_eq!(
+        pids::make_angle(0.0, 0.0, 0.0, 0.0, 3.0, &LoopConfig { rate_hz: 200 }, &d)
+            .err()
+            .unwrap()
+            .field,
+        "dterm_lpf1_dyn_max_hz");
+
+    // Failsafe couldn't level
+    assert_eq!(
+        pids::make_angle(0.0125, 0.0103, 0.0000625, 0.0001756, 0.0, &LOOP_CONFIG, &d)
+            .err()
+            .unwrap()
+            .field,
+        "k_level_p");
+}
+
+#[test]
+fn invalid_alt_hold_configs_are_rejected() {
+
+    let d = pids::DEFAULT_ALT_HOLD_CONFIG;
+
+    assert_eq!(alt_hold_error(AltHoldConfig { pilot_velz_max: 0.0, ..d }), "pilot_velz_max");
+    assert_eq!(alt_hold_error(AltHoldConfig { stick_deadband: 0.95, ..d }), "stick_deadband");
+    assert_eq!(alt_hold_error(AltHoldConfig { windup_max: -1.0, ..d }), "windup_max");
+}
+
+#[test]
+fn output_limit_is_configurable() {
+
+    let vstate = VehicleState {
+        x: 0.0, dx: 0.0, y: 0.0, dy: 0.0, z: 0.0, dz: 0.0,
+        phi: 0.0, dphi: 0.0, theta: 0.0, dtheta: 0.0, psi: 0.0, dpsi: 0.0
+    };
+
+    let demands = Demands { throttle: 0.5, roll: 1.0, pitch: 0.0, yaw: 0.0 };
+
+    let roll = |config: AnglePidConfig| {
+        let mut pid = make_angle(&config).unwrap();
+        pids::set_level_mode(&mut pid, LevelMode::Acro);
+        pids::update(&mut pid, 0, demands.clone(), vstate, false).roll
+    };
+
+    let limited = roll(AnglePidConfig { limit_cyclic: 5.0, ..pids::DEFAULT_ANGLE_CONFIG });
+
+    assert_eq!(limited, 0.005);
+    assert!(roll(pids::DEFAULT_ANGLE_CONFIG) > limited);
+}
+
+#[test]
+fn alt_hold_throttle_follows_config() {
+
+    let d = pids::DEFAULT_ALT_HOLD_CONFIG;
+
+    // Full descent at the configured maximum rate
+    assert_eq!(pids::alt_hold_throttle(&d, -d.pilot_velz_max), 0.0);
+
+    let slower = AltHoldConfig { pilot_velz_max: 5.0, ..d };
+
+    assert_eq!(pids::alt_hold_throttle(&slower, -2.5), 0.25);
+}
@@ -0,0 +1,130 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::rates;
+use hackflight::rates::RateProfile;
+use hackflight::rates::Rates;
+
+const STICKS: [f32; 5] = [0.25, 0.5, 0.75, 1.0, -0.5];
+
+// Reference values from Betaflight's rc.c, evaluated with its integer
+// rate-profile settings
+fn check(rates: Rates, expected: [f32; 5]) {
+
+    for (stick, e) in STICKS.iter().zip(expected) {
+
+        let actual = rates.apply(*stick);
+
+        assert!((actual - e).abs() < 0.01, "{:?} at {}: {} != {}", rates, stick, actual, e);
+    }
+}
+
+#[test]
+fn betaflight_rates() {
+
+    check(
+        Rates::Betaflight { rc_rate: 1.0, super_rate: 0.7, expo: 0.0 },
+        [60.606, 153.846, 315.789, 666.667, -153.846]);
+
+    check(
+        Rates::Betaflight { rc_rate: 1.2, super_rate: 0.75, expo: 0.3 },
+        [52.038, 141.6, 340.071, 960.0, -141.6]);
+
+    // RC rate above 2.0 is incremental
+    check(
+        Rates::Betaflight { rc_rate: 2.5, super_rate: 0.0, expo: 0.0 },
+        [488.5, 977.0, 1465.5, 1954.0, -977.0]);
+}
+
+#[test]
+fn actual_rates() {
+
+    check(
+        Rates::Actual { center_sensitivity: 70.0, max_rate: 670.0, expo: 0.54 },
+        [34.829, 109.062, 265.415, 670.0, -109.062]);
+}
+
+#[test]
+fn kiss_rates() {
+
+    check(
+        Rates::Kiss { rc_rate: 1.0, rate: 0.7, rc_curve: 0.0 },
+        [60.606, 153.846, 315.789, 666.667, -153.846]);
+
+    check(
+        Rates::Kiss { rc_rate: 1.2, rate: 0.72, rc_curve: 0.3 },
+        [52.591, 145.312, 339.946, 857.143, -145.312]);
+}
+
+#[test]
+fn quick_rates() {
+
+    check(
+        Rates::Quick { rc_rate: 1.0, max_rate: 670.0, expo: 0.0 },
+        [60.633, 154.023, 316.535, 670.0, -154.023]);
+
+    check(
+        Rates::Quick { rc_rate: 1.1, max_rate: 800.0, expo: 0.4 },
+        [62.026, 147.404, 299.235, 800.0, -147.404]);
+}
+
+#[test]
+fn default_matches_original_curve() {
+
+    for k in -10..=10 {
+
+        let command = k as f32 / 10.0;
+
+        let original = 670.0 * (command * 0.104 + (1.0 - 0.104) * command * command.abs());
+
+        assert!((rates::DEFAULT_RATES.apply(command) - original).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn output_is_limited() {
+
+    let rates = Rates::Betaflight { rc_rate: 2.55, super_rate: 0.99, expo: 0.0 };
+
+    assert_eq!(rates.apply(1.0), rates::SETPOINT_RATE_LIMIT);
+    assert_eq!(rates.apply(-2.0), -rates::SETPOINT_RATE_LIMIT);
+}
+
+#[test]
+fn invalid_rates_are_rejected() {
+
+    let field = |rates: Rates| rates.validate().expect_err("rates should be rejected").field;
+
+    // Would make every setpoint NaN
+    assert_eq!(field(Rates::Quick { rc_rate: 0.0, max_rate: 670.0, expo: 0.0 }), "rc_rate");
+    assert_eq!(field(Rates::Quick { rc_rate: 1.0, max_rate: 150.0, expo: 0.0 }), "max_rate");
+    assert_eq!(field(Rates::Quick { rc_rate: 1.0, max_rate: 670.0, expo: f32::NAN }), "expo");
+
+    assert_eq!(field(Rates::Betaflight { rc_rate: 1.0, super_rate: 1.0, expo: 0.0 }), "super_rate");
+    assert_eq!(field(Rates::Betaflight { rc_rate: -1.0, super_rate: 0.7, expo: 0.0 }), "rc_rate");
+    assert_eq!(field(Rates::Actual { center_sensitivity: 70.0, max_rate: 0.0, expo: 0.5 }), "max_rate");
+    assert_eq!(field(Rates::Kiss { rc_rate: 1.0, rate: 0.7, rc_curve: 1.5 }), "rc_curve");
+
+    let profile = RateProfile {
+        yaw: Rates::Quick { rc_rate: 0.0, max_rate: 670.0, expo: 0.0 },
+        ..rates::DEFAULT_PROFILE
+    };
+
+    assert_eq!(profile.validate().err().unwrap().field, "rc_rate");
+    assert!(rates::DEFAULT_PROFILE.validate().is_ok());
+}
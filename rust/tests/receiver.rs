@@ -0,0 +1,133 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::Demands;
+use hackflight::receiver;
+use hackflight::receiver::AuxSwitch;
+
+// SBUS channel range
+const SRC_MIN: u16 = 172;
+const SRC_MAX: u16 = 1811;
+
+fn assert_demands(demands: &Demands, expected: [f32; 4]) {
+
+    let actual = [demands.throttle, demands.roll, demands.pitch, demands.yaw];
+
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+    }
+}
+
+#[test]
+fn demands_match_cpp_receiver_task() {
+
+    // Outputs of C++ ReceiverTask::modifyDemands() for the same inputs
+    let cases = [
+        ([172, 992, 992, 992, 172, 1811], [0.0, 0.000610, 0.000610, -0.000610]),
+        ([1811, 1811, 172, 1811, 992, 172], [1.0, 1.0, -1.0, -1.0]),
+        ([600, 1300, 700, 1200, 992, 992], [0.222, 0.376449, -0.355705, -0.254423]),
+        ([1000, 1100, 1000, 1000, 992, 992], [0.478, 0.132398, 0.010372, -0.010372])
+    ];
+
+    let mut rx = receiver::make();
+
+    for (channels, expected) in cases {
+
+        rx.set_values(&channels, 0, false, SRC_MIN, SRC_MAX);
+
+        assert_demands(&rx.get_demands(), expected);
+    }
+}
+
+#[test]
+fn axes_hold_last_good_values_between_frames() {
+
+    let mut rx = receiver::make();
+
+    rx.set_values(&[1000, 1100, 1000, 1000, 992, 992], 0, false, SRC_MIN, SRC_MAX);
+
+    let first = rx.get_demands();
+
+    // No new frame
+    assert_demands(&rx.get_demands(), [first.throttle, first.roll, first.pitch, first.yaw]);
+}
+
+#[test]
+fn throttle_expo_table_is_linear_by_default() {
+
+    let mut rx = receiver::make();
+
+    for (raw, expected) in [(1000, 0.0), (1050, 0.0), (1240, 0.2), (1525, 0.5), (2000, 1.0)] {
+
+        rx.set_values(&[raw, 1500, 1500, 1500, 1000, 1000], 0, false, 1000, 2000);
+
+        let demands = rx.get_demands();
+
+        assert!((demands.throttle - expected).abs() < 1e-6, "{} => {}", raw, demands.throttle);
+    }
+
+    assert!(!rx.throttle_is_down());
+
+    rx.set_values(&[1049, 1500, 1500, 1500, 1000, 1000], 0, false, 1000, 2000);
+
+    assert!(rx.throttle_is_down());
+}
+
+#[test]
+fn signal_times_out() {
+
+    let mut rx = receiver::make();
+
+    rx.set_values(&[172; 6], 1_000_000, false, SRC_MIN, SRC_MAX);
+
+    assert!(rx.have_signal(1_000_000));
+    assert!(rx.have_signal(1_029_999));
+    assert!(!rx.have_signal(1_030_000));
+
+    rx.set_values(&[172; 6], 1_040_000, true, SRC_MIN, SRC_MAX);
+
+    assert!(!rx.have_signal(1_040_000));
+}
+
+#[test]
+fn aux_switch_positions() {
+
+    let mut rx = receiver::make();
+
+    rx.set_values(&[1000, 1500, 1500, 1500, 1000, 1500], 0, false, 1000, 2000);
+
+    assert_eq!(rx.aux_switch(0), AuxSwitch::Low);
+    assert_eq!(rx.aux_switch(1), AuxSwitch::Mid);
+    assert_eq!(rx.aux(1), 1500.0);
+
+    rx.set_values(&[1000, 1500, 1500, 1500, 2000, 1800], 0, false, 1000, 2000);
+
+    assert_eq!(rx.aux_switch(0), AuxSwitch::High);
+    assert_eq!(rx.aux_switch(1), AuxSwitch::High);
+}
+
+#[test]
+fn empty_source_range_gives_neutral_sticks() {
+
+    let mut rx = receiver::make();
+
+    rx.set_values(&[1000, 1811, 172, 1200, 992, 992], 0, false, 992, 992);
+
+    // Mid-stick throttle, after the low-throttle deadband
+    assert_demands(&rx.get_demands(), [0.473, 0.0, 0.0, 0.0]);
+}
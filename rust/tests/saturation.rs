@@ -0,0 +1,162 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::Demands;
+use hackflight::Mixer;
+use hackflight::Motors;
+use hackflight::mixers;
+use hackflight::mixers::hexx;
+use hackflight::mixers::quadxbf;
+use hackflight::mixers::quadxbf::QuadXbf;
+use hackflight::mixers::saturation;
+use hackflight::mixers::saturation::Priority;
+use hackflight::mixers::saturation::SaturationConfig;
+
+const AIRMODE: SaturationConfig = SaturationConfig { priority: Priority::RollPitch, airmode: true };
+
+const EQUAL: SaturationConfig = SaturationConfig { priority: Priority::Equal, airmode: false };
+
+fn demands(throttle: f32, roll: f32, pitch: f32, yaw: f32) -> Demands {
+
+    Demands { throttle, roll, pitch, yaw }
+}
+
+fn quad(demands: &Demands, config: &SaturationConfig) -> Motors {
+
+    saturation::mix_limited(&quadxbf::MOTORS, demands, config)
+}
+
+// Roll and yaw as the QuadXbf motors produce them: left minus right, and
+// clockwise minus counter-clockwise
+fn roll_of(m: &Motors) -> f32 {
+
+    (m.values[2] + m.values[3] - m.values[0] - m.values[1]) / 4.0
+}
+
+fn yaw_of(m: &Motors) -> f32 {
+
+    (m.values[0] + m.values[3] - m.values[1] - m.values[2]) / 4.0
+}
+
+fn close(a: f32, b: f32) -> bool {
+
+    (a - b).abs() < 1e-5
+}
+
+#[test]
+fn unsaturated_mix_is_unchanged() {
+
+    let d = demands(0.5, 0.1, -0.2, 0.05);
+
+    for config in [saturation::DEFAULT_CONFIG, AIRMODE, EQUAL] {
+        assert_eq!(quad(&d, &config), mixers::mix(&quadxbf::MOTORS, &d));
+    }
+}
+
+#[test]
+fn full_throttle_keeps_roll_authority() {
+
+    let d = demands(1.0, 0.2, 0.0, 0.0);
+
+    let raw = mixers::mix(&quadxbf::MOTORS, &d);
+
+    assert!(raw.values[2] > 1.0);
+
+    // Throttle shifts down to make room; roll is intact
+    let motors = QuadXbf { saturation: saturation::DEFAULT_CONFIG }.get_motors(&d);
+
+    assert_eq!(motors.as_slice(), [0.6, 0.6, 1.0, 1.0]);
+    assert!(close(roll_of(&motors), 0.2));
+}
+
+#[test]
+fn yaw_gives_way_to_roll_and_pitch() {
+
+    // Needs 0.6 + 0.6 of the motors' 1.0 of range
+    let d = demands(0.5, 0.3, 0.0, 0.3);
+
+    let motors = quad(&d, &saturation::DEFAULT_CONFIG);
+
+    assert!(close(roll_of(&motors), 0.3));
+    assert!(close(yaw_of(&motors), 0.2));
+
+    // ... unless they're equal, when both shrink alike
+    let motors = quad(&d, &EQUAL);
+
+    assert!(close(roll_of(&motors), 0.25));
+    assert!(close(yaw_of(&motors), 0.25));
+}
+
+#[test]
+fn oversized_corrections_are_scaled_back() {
+
+    let d = demands(0.5, 0.8, 0.4, 0.0);
+
+    for config in [saturation::DEFAULT_CONFIG, EQUAL] {
+
+        let motors = quad(&d, &config);
+
+        // Corrections spanning 2.4 shrink to fit, keeping their proportions
+        for (actual, expected) in motors.as_slice().iter().zip([1.0 / 3.0, 0.0, 1.0, 2.0 / 3.0]) {
+            assert!(close(*actual, expected), "{:?}", motors);
+        }
+    }
+}
+
+#[test]
+fn airmode_keeps_control_at_zero_throttle() {
+
+    let d = demands(0.0, 0.2, 0.0, 0.0);
+
+    // Motors that should slow down can't, so half the roll is lost
+    let motors = quad(&d, &saturation::DEFAULT_CONFIG);
+
+    assert_eq!(motors.as_slice(), [0.0, 0.0, 0.2, 0.2]);
+    assert!(close(roll_of(&motors), 0.1));
+
+    // Airmode raises the throttle instead
+    let motors = quad(&d, &AIRMODE);
+
+    assert!(close(roll_of(&motors), 0.2));
+    assert!(close(motors.values[0], 0.0));
+
+    // With nothing to correct, the motors stay off
+    assert_eq!(quad(&demands(0.0, 0.0, 0.0, 0.0), &AIRMODE).as_slice(), [0.0; 4]);
+}
+
+#[test]
+fn motors_stay_in_range() {
+
+    let mut mixer = mixers::make_matrix(&hexx::MOTORS).unwrap();
+
+    for config in [saturation::DEFAULT_CONFIG, AIRMODE, EQUAL] {
+
+        mixer.set_saturation(&config);
+
+        for k in 0..1000 {
+
+            // Deterministic spread of demands, well past saturation
+            let x = |m: u32| ((k * m % 997) as f32 / 997.0) * 4.0 - 2.0;
+
+            let motors = mixer.get_motors(&demands(x(1) / 2.0 + 0.5, x(7), x(13), x(31)));
+
+            assert_eq!(motors.count, 6);
+            assert!(motors.as_slice().iter().all(|v| (0.0..=1.0).contains(v)), "{:?}", motors);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+/*
+   Copyright (c) 2022 Simon D. Levy
+
+   This file is part of Hackflight.
+
+   Hackflight is free software: you can redistribute it and/or modify it under the
+   terms of the GNU General Public License as published by the Free Software
+   Foundation, either version 3 of the License, or (at your option) any later
+   version.
+
+   Hackflight is distributed in the hope that it will be useful, but WITHOUT ANY
+   WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+   PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License along with
+   Hackflight. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hackflight::receiver;
+use hackflight::receiver::sbus;
+
+// Raw frame bytes with all channels centered at 992, as sent by FrSky
+// receivers with sticks centered
+const CENTERED: [u8; sbus::FRAME_SIZE] = [
+    0x0F, 0xE0, 0x03, 0x1F, 0xF8, 0xC0, 0x07, 0x3E, 0xF0, 0x81, 0x0F, 0x7C,
+    0xE0, 0x03, 0x1F, 0xF8, 0xC0, 0x07, 0x3E, 0xF0, 0x81, 0x0F, 0x7C, 0x00,
+    0x00
+];
+
+// Raw frame bytes with throttle down (172), roll full right (1811), pitch at
+// 1000, yaw at 992, aux1 at 1811, channel 17 set, and failsafe active
+const MIXED: [u8; sbus::FRAME_SIZE] = [
+    0x0F, 0xAC, 0x98, 0x38, 0xFA, 0xC0, 0x37, 0x71, 0xF0, 0x81, 0x0F, 0x7C,
+    0xE0, 0x03, 0x1F, 0xF8, 0xC0, 0x07, 0x3E, 0xF0, 0x81, 0x0F, 0x7C, 0x09,
+    0x00
+];
+
+fn parse_all(bytes: &[u8]) -> Vec<sbus::Frame> {
+
+    let mut decoder = sbus::make_decoder();
+
+    bytes.iter().filter_map(|&c| decoder.parse(c)).collect()
+}
+
+#[test]
+fn centered_frame_is_decoded() {
+
+    let frames = parse_all(&CENTERED);
+
+    assert_eq!(frames.len(), 1);
+
+    let frame = frames[0];
+
+    assert_eq!(frame.channels, [992; sbus::NUM_CHANNELS]);
+    assert!(!frame.ch17 && !frame.ch18 && !frame.lost_frame && !frame.failsafe);
+}
+
+#[test]
+fn channels_and_flags_are_unpacked() {
+
+    let frame = parse_all(&MIXED)[0];
+
+    assert_eq!(frame.channels[..6], [172, 1811, 1000, 992, 1811, 992]);
+    assert_eq!(frame.channels[6..], [992; 10]);
+
+    assert!(frame.ch17);
+    assert!(!frame.ch18);
+    assert!(!frame.lost_frame);
+    assert!(frame.failsafe);
+}
+
+#[test]
+fn decoder_resyncs_after_garbage_and_bad_footer() {
+
+    let mut bad_footer = CENTERED;
+    bad_footer[24] = 0x55;
+
+    let mut stream = vec![0x12, 0x0F, 0x34];
+    stream.extend(&bad_footer);
+    stream.extend(&CENTERED);
+    stream.extend(&MIXED);
+
+    let frames = parse_all(&stream);
+
+    // The frame after the bad footer is skipped, since its header does not
+    // follow a footer; the one after it is found again
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].channels[0], 172);
+
+    // SBUS2 footers are accepted
+    let mut sbus2 = CENTERED;
+    sbus2[24] = 0x14;
+
+    let mut stream = sbus2.to_vec();
+    stream.extend(&MIXED);
+
+    assert_eq!(parse_all(&stream).len(), 2);
+}
+
+#[test]
+fn frames_feed_the_receiver() {
+
+    let mut rx = receiver::make();
+
+    parse_all(&CENTERED)[0].set_receiver(&mut rx, 1000);
+
+    assert!(rx.have_signal(1000));
+
+    let demands = rx.get_demands();
+
+    assert!((demands.throttle - 0.473).abs() < 1e-6);
+    assert!(demands.roll.abs() < 1e-3);
+
+    // Failsafe means no signal
+    parse_all(&MIXED)[0].set_receiver(&mut rx, 2000);
+
+    assert!(!rx.have_signal(2000));
+    assert!(rx.throttle_is_down());
+}
+
+#[test]
+fn lost_frame_is_skipped() {
+
+    let mut rx = receiver::make();
+
+    parse_all(&CENTERED)[0].set_receiver(&mut rx, 1000);
+
+    // Throttle down, but stale
+    let lost = sbus::Frame { lost_frame: true, failsafe: false, ..parse_all(&MIXED)[0] };
+
+    lost.set_receiver(&mut rx, 2000);
+
+    assert!(rx.have_signal(2000));
+    assert!(!rx.throttle_is_down());
+}
+
+#[test]
+fn failsafe_is_loss_of_signal() {
+
+    let mut rx = receiver::make();
+
+    parse_all(&CENTERED)[0].set_receiver(&mut rx, 1000);
+
+    // Receivers set the lost-frame flag along with failsafe
+    let failsafe = sbus::Frame { lost_frame: true, ..parse_all(&MIXED)[0] };
+
+    failsafe.set_receiver(&mut rx, 2000);
+
+    assert!(!rx.have_signal(2000));
+}